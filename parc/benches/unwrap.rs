@@ -0,0 +1,68 @@
+//! Compare the blocking waits of `ParentArc`, run with `--no-default-features` to measure the
+//! spinning fallback against the default parking implementation.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use parc::ParentArc;
+
+// Readers upgrade in a loop until the parent locks them out
+fn spawn_readers(parent: &ParentArc<AtomicUsize>, n: usize) -> Vec<thread::JoinHandle<()>> {
+    let started = Arc::new(AtomicUsize::new(0));
+    let handles = (0..n)
+        .map(|_| {
+            let weak = ParentArc::downgrade(parent);
+            let started = Arc::clone(&started);
+            thread::spawn(move || {
+                started.fetch_add(1, Ordering::SeqCst);
+                while let Ok(child) = weak.upgrade() {
+                    child.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+    while started.load(Ordering::SeqCst) != n {
+        thread::yield_now();
+    }
+    handles
+}
+
+fn block_into_inner(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_into_inner");
+    for readers in [1, 4, 16] {
+        group.bench_with_input(BenchmarkId::from_parameter(readers), &readers, |b, &n| {
+            b.iter(|| {
+                let parent = ParentArc::new(AtomicUsize::new(0));
+                let handles = spawn_readers(&parent, n);
+                let _ = parent.block_into_inner();
+                handles.into_iter().for_each(|h| h.join().unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn held_child(c: &mut Criterion) {
+    // The parent waits for a child held for a fixed time, this measures the cost of the wait
+    c.bench_function("held_child", |b| {
+        b.iter(|| {
+            let parent = ParentArc::new(AtomicUsize::new(0));
+            let weak = ParentArc::downgrade(&parent);
+            let (tx, rx) = std::sync::mpsc::channel();
+            let handle = thread::spawn(move || {
+                let child = weak.upgrade().unwrap();
+                tx.send(()).unwrap();
+                thread::sleep(std::time::Duration::from_micros(200));
+                drop(child);
+            });
+            rx.recv().unwrap();
+            let _ = parent.block_into_inner();
+            handle.join().unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, block_into_inner, held_child);
+criterion_main!(benches);
@@ -14,23 +14,179 @@
 mod imports {
     extern crate alloc;
     pub(super) use alloc::boxed::Box;
+    pub(super) use alloc::sync::{Arc, Weak};
+    pub(super) use alloc::vec::Vec;
 }
 
 #[cfg(feature = "std")]
 mod imports {
     extern crate std;
     pub(super) use std::boxed::Box;
-    pub(super) use std::fmt;
+    pub(super) use std::sync::{Arc, Weak};
+    pub(super) use std::time::{Duration, Instant};
+    pub(super) use std::vec::Vec;
 }
 
 use imports::*;
 
-use core::mem;
+// Blocking waits for the children to drop, the std thread parks until the last child wakes it
+#[cfg(all(feature = "std", not(loom)))]
+mod wait {
+    extern crate std;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+    use std::time::Instant;
+
+    use super::{AtomicUsize, AtomicWaker, Ordering, Waiter, Waiters};
+
+    struct Unparker(Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    pub(super) fn until_released(strong: &AtomicUsize, waker: &AtomicWaker) {
+        if strong.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let unparker = Waker::from(Arc::new(Unparker(thread::current())));
+        loop {
+            // Register before checking so the last child cannot drop in between unnoticed
+            waker.register(&unparker);
+            if strong.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            thread::park();
+        }
+    }
+
+    // Returns false if the children are still alive at the deadline
+    pub(super) fn until_released_before(
+        strong: &AtomicUsize,
+        waker: &AtomicWaker,
+        deadline: Instant,
+    ) -> bool {
+        if strong.load(Ordering::SeqCst) == 0 {
+            return true;
+        }
+        let unparker = Waker::from(Arc::new(Unparker(thread::current())));
+        loop {
+            waker.register(&unparker);
+            if strong.load(Ordering::SeqCst) == 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    // Waits of the readers and writers of a RwParentArc, woken through the waiters of the header
+    pub(super) fn until(waiters: &Waiters, mut ready: impl FnMut() -> bool) {
+        if ready() {
+            return;
+        }
+        let waiter = Arc::new(Waiter::new());
+        let unparker = Waker::from(Arc::new(Unparker(thread::current())));
+        loop {
+            waiters.register(&waiter, &unparker);
+            if ready() {
+                break;
+            }
+            thread::park();
+        }
+    }
+}
+
+// Blocking waits for the children to drop, without a scheduler the thread spins with backoff
+#[cfg(all(not(feature = "std"), not(loom)))]
+mod wait {
+    use super::{AtomicUsize, AtomicWaker, Ordering, Waiters};
+
+    const MAX_STEP: u32 = 10;
+
+    pub(super) fn until_released(strong: &AtomicUsize, _: &AtomicWaker) {
+        let mut step = 0;
+        while strong.load(Ordering::SeqCst) != 0 {
+            relax(&mut step);
+        }
+    }
+
+    pub(super) fn until(_: &Waiters, mut ready: impl FnMut() -> bool) {
+        let mut step = 0;
+        while !ready() {
+            relax(&mut step);
+        }
+    }
+
+    fn relax(step: &mut u32) {
+        for _ in 0..1 << *step {
+            core::hint::spin_loop();
+        }
+        *step = core::cmp::min(*step + 1, MAX_STEP);
+    }
+}
+
+// Model checked waits, spinning yields to the other threads explored by loom
+#[cfg(loom)]
+mod wait {
+    #[cfg(feature = "std")]
+    use super::Instant;
+    use super::{AtomicUsize, AtomicWaker, Ordering, Waiters};
+
+    pub(super) fn until_released(strong: &AtomicUsize, _: &AtomicWaker) {
+        while strong.load(Ordering::SeqCst) != 0 {
+            loom::thread::yield_now();
+        }
+    }
+
+    pub(super) fn until(_: &Waiters, mut ready: impl FnMut() -> bool) {
+        while !ready() {
+            loom::thread::yield_now();
+        }
+    }
+
+    // Time does not pass in a model, the children are always waited for
+    #[cfg(feature = "std")]
+    pub(super) fn until_released_before(
+        strong: &AtomicUsize,
+        waker: &AtomicWaker,
+        _: Instant,
+    ) -> bool {
+        until_released(strong, waker);
+        true
+    }
+}
+
+use core::fmt;
+use core::future::Future;
+use core::hash::{Hash, Hasher};
+use core::mem::{self, ManuallyDrop};
 use core::ops;
 use core::pin::Pin;
 use core::ptr;
 use core::ptr::NonNull;
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(loom))]
+use core::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+#[cfg(loom)]
+use loom::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use atomic_waker::AtomicWaker;
+
+// Parent and children sharing the data between readers and writers
+mod rw;
+
+pub use rw::{ReadChildArc, RwIntoInner, RwLockWeak, RwParentArc, WriteChildArc};
 
 /// Result Type for [`try_into_inner`]
 ///
@@ -46,7 +202,6 @@ pub enum TryUnwrapError<T> {
     WouldBlock(ParentArc<T>),
 }
 
-#[cfg(feature = "std")]
 impl<T> fmt::Debug for TryUnwrapError<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -56,6 +211,16 @@ impl<T> fmt::Debug for TryUnwrapError<T> {
     }
 }
 
+/// Errors for [`upgrade`](struct.LockWeak.html#method.upgrade)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeError {
+    /// The parent has locked its weak references, they can upgrade again once it unlocks
+    Locked,
+
+    /// The parent has been consumed or dropped, the weak reference will never upgrade again
+    Dead,
+}
+
 /// Owner of multiple atomically reference counted children.
 ///
 /// The type `ParentArc<T>` allows for shared access of the inner data by multiple threads through LockWeak references.
@@ -70,6 +235,13 @@ impl<T> fmt::Debug for TryUnwrapError<T> {
 /// The [`LockWeak`](struct.LockWeak) can be passed around through threads safely because they do
 /// not guaranty the existence of the data at upgrade time.
 /// `ParentArc<T>` makes it thread safe to have multiple owned reference of the same data, but it doesn't add thread safety to its data.
+/// Like `Arc<T>` it is `Send` and `Sync` when `T` is `Send` and `Sync`, its weak and child
+/// references only share `&T` and require `T: Sync`.
+///
+/// # Ownership
+/// Moving a `ParentArc<T>` to another thread hands off the ownership, the weak and child
+/// references stay attached and the new owner locks and consumes them. To share the ownership
+/// instead, [`split`](#method.split) it into [`CoParent`](struct.CoParent.html)s.
 pub struct ParentArc<T> {
     ptr: NonNull<Womb<T>>,
 }
@@ -115,14 +287,13 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn lock(&self) {
-        let lock = &self.inner().lock;
-        while lock.compare_and_swap(false, true, Ordering::Release) {}
+        self.header().lock_children();
     }
 
     /// Check wether the [`LockWeak`](struct.LockWeak.html)s are locked. Since only the Parent can
     /// unlock it is considered a somewhat trustable result.
     pub fn is_locked(&self) -> bool {
-        self.inner().lock.load(Ordering::Relaxed)
+        self.header().lock.load(Ordering::Relaxed)
     }
 
     /// Unlocks all [`LockWeak`](struct.LockWeak.html) of this [`ParentArc`](struct.ParentArc.html),
@@ -143,8 +314,9 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn unlock(&self) {
-        let lock = &self.inner().lock;
-        while lock.compare_and_swap(true, false, Ordering::Release) {}
+        let this = self.header();
+        this.lock.store(false, Ordering::SeqCst);
+        this.waiters.wake_all();
     }
 
     /// Downgrade a [`ParentArc`](struct.ParentArc.html) into a [`LockWeak`](struct.LockWeak.html)
@@ -159,7 +331,11 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn downgrade(other: &Self) -> LockWeak<T> {
-        LockWeak { ptr: other.ptr }
+        other.header().retain();
+        LockWeak {
+            header: other.ptr.cast(),
+            data: Womb::data(other.ptr),
+        }
     }
 
     /// Tries to downgrade a [`ParentArc`](struct.ParentArc.html) into a [`LockWeak`](struct.LockWeak.html) if the inner state allows the latter to upgrade.
@@ -178,18 +354,21 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn try_downgrade(other: &Self) -> Option<LockWeak<T>> {
-        if other.inner().lock.load(Ordering::Relaxed) {
+        if other.header().lock.load(Ordering::Relaxed) {
             return None;
         }
-        Some(LockWeak { ptr: other.ptr })
+        Some(Self::downgrade(other))
     }
 
     /// Blocks the thread until all [`ChildArc`](struct.ChildArc.html) of this instance
     /// have dropped, returning the underlying data.
     ///
+    /// With the `std` feature the thread is parked and woken by the last child, otherwise it spins
+    /// with an exponential backoff.
+    ///
     /// # Safety
     ///
-    /// This call will indefinitly spin if a child has not droped correctly.
+    /// This call will indefinitly wait if a child has not droped correctly.
     ///
     /// # Examples
     /// ```rust
@@ -208,18 +387,106 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn block_into_inner(self) -> T {
-        let this = self.inner();
+        let this = self.header();
 
         self.lock();
-        while this.strong.load(Ordering::Acquire) != 0 {}
+        wait::until_released(&this.strong, &this.waker);
 
-        unsafe {
-            let elem = ptr::read(&this.data);
-            mem::forget(self);
-            elem
+        unsafe { self.take() }
+    }
+
+    /// Bounded version of [`block_into_inner`](#method.block_into_inner), it waits at most
+    /// `timeout` for the [`ChildArc`](struct.ChildArc.html)s to drop.
+    ///
+    /// On timeout the parent is returned in
+    /// [`WouldBlock`](enum.TryUnwrapError.html#variant.WouldBlock), the
+    /// [`LockWeak`](struct.LockWeak.html)s are unlocked unless they were already locked before the
+    /// call.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak, TryUnwrapError::*};
+    /// use std::sync::Mutex;
+    /// use std::time::Duration;
+    /// fn main() {
+    ///     let parent = ParentArc::new(Mutex::new(true));
+    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+    ///
+    ///     let child = weak.upgrade().unwrap(); // Leaked reader
+    ///     let parent = match parent.into_inner_timeout(Duration::from_millis(10)) {
+    ///         Err(WouldBlock(parent)) => parent,
+    ///         _ => unreachable!(),
+    ///     };
+    ///     assert!(!parent.is_locked());
+    ///
+    ///     drop(child);
+    ///     let _: Mutex<bool> = parent.into_inner_timeout(Duration::from_millis(10)).unwrap();
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn into_inner_timeout(self, timeout: Duration) -> TryUnwrapResult<T> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.into_inner_deadline(deadline),
+            None => Ok(self.block_into_inner()),
         }
     }
 
+    /// Version of [`into_inner_timeout`](#method.into_inner_timeout) that waits until `deadline`.
+    #[cfg(feature = "std")]
+    pub fn into_inner_deadline(self, deadline: Instant) -> TryUnwrapResult<T> {
+        let this = self.header();
+
+        let locked = this.lock_children();
+        if wait::until_released_before(&this.strong, &this.waker, deadline) {
+            unsafe { Ok(self.take()) }
+        } else {
+            // Leave a lock taken by the caller in place
+            if !locked {
+                self.unlock();
+            }
+            Err(TryUnwrapError::WouldBlock(self))
+        }
+    }
+
+    /// Asynchronous version of [`block_into_inner`](#method.block_into_inner), the returned
+    /// future locks the [`LockWeak`](struct.LockWeak.html)s and is woken when the last
+    /// [`ChildArc`](struct.ChildArc.html) drops instead of spinning.
+    ///
+    /// Dropping the future before it completes does not wait for the children, the weak references
+    /// fail with [`Dead`](enum.UpgradeError.html#variant.Dead) and the last child drops the data on
+    /// its own thread.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak};
+    /// use std::sync::Mutex;
+    /// fn main() {
+    ///     let parent = ParentArc::new(Mutex::new(true));
+    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+    ///
+    ///     let handle = std::thread::spawn(move || {
+    ///         if let Ok(child) = weak.upgrade() {
+    ///             *child.lock().unwrap() = false;
+    ///         }
+    ///     });
+    ///
+    ///     let _: Mutex<bool> = futures::executor::block_on(parent.into_inner_async());
+    ///     handle.join().unwrap();
+    /// }
+    /// ```
+    pub fn into_inner_async(self) -> IntoInner<T>
+    where
+        T: Send,
+    {
+        self.lock_into_inner()
+    }
+
+    // Without the `Send` bound, for the wrappers whose children only leave the thread when `T` is
+    pub(crate) fn lock_into_inner(self) -> IntoInner<T> {
+        self.lock();
+        IntoInner { parent: Some(self) }
+    }
+
     /// Non-blocking version of [`block_into_inner`](#method.block_into_inner). It is advised to
     /// call [`lock`](#method.lock) before calling this one, unless you know for sure there are no
     /// [`ChildArc`](struct.ChildArc.html) alive at this instance.
@@ -262,26 +529,107 @@ impl<T> ParentArc<T> {
     /// }
     /// ```
     pub fn try_unwrap(other: Self) -> TryUnwrapResult<T> {
-        let this = other.inner();
+        let this = other.header();
 
-        if !this.lock.load(Ordering::Relaxed) && this.strong.load(Ordering::Relaxed) > 0 {
-            // Check for non-null count and unlock state
-            return Err(TryUnwrapError::WouldLock(other));
-        }
-        if this.strong.load(Ordering::Relaxed) != 0 {
+        // Lock before checking the count so a concurrent upgrade either is counted or fails
+        let locked = this.lock_children();
+        if this.strong.load(Ordering::SeqCst) != 0 {
+            if !locked {
+                other.unlock();
+                return Err(TryUnwrapError::WouldLock(other));
+            }
             return Err(TryUnwrapError::WouldBlock(other));
         }
 
-        unsafe {
-            let elem = ptr::read(&this.data);
-            mem::forget(other);
-            Ok(elem)
-        }
+        unsafe { Ok(other.take()) }
+    }
+
+    /// Split the ownership between `n` [`CoParent`](struct.CoParent.html)s, the weak and child
+    /// references stay attached.
+    ///
+    /// The data can only be consumed once all co-parents agree to it, each of them gives up its
+    /// share in [`into_parent`](struct.CoParent.html#method.into_parent) and the last one gets the
+    /// `ParentArc<T>` back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::ParentArc;
+    /// use std::sync::Mutex;
+    /// fn main() {
+    ///     let parent = ParentArc::new(Mutex::new(0));
+    ///     let weak = ParentArc::downgrade(&parent);
+    ///
+    ///     let mut co_parents = parent.split(2);
+    ///     let other = co_parents.pop().unwrap();
+    ///     let handle = std::thread::spawn(move || other.block_into_inner());
+    ///
+    ///     *weak.upgrade().unwrap().lock().unwrap() += 1;
+    ///     let last = co_parents.pop().unwrap().block_into_inner();
+    ///     let first = handle.join().unwrap();
+    ///
+    ///     // Only the co-parent that gave up its share last got the data
+    ///     assert_eq!(first.or(last).unwrap().into_inner().unwrap(), 1);
+    /// }
+    /// ```
+    pub fn split(self, n: usize) -> Vec<CoParent<T>> {
+        assert!(n != 0, "cannot split a parent into zero co-parents");
+        let ptr = self.ptr;
+        mem::forget(self);
+
+        let this = unsafe { ptr.as_ref() };
+        this.header.parents.store(n, Ordering::Relaxed);
+        CoParent::share(ptr, n)
     }
 
     fn inner(&self) -> &Womb<T> {
         unsafe { self.ptr.as_ref() } // Ok to do this because we own the data
     }
+
+    fn header(&self) -> &Header {
+        &self.inner().header
+    }
+
+    /// Check wether two parents own the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    // Caller must ensure there are no children left
+    unsafe fn take(self) -> T {
+        let ptr = self.ptr;
+        mem::forget(self);
+
+        // Weak references stay locked once the data is gone
+        let this = ptr.as_ref();
+        this.header.lock_children();
+        this.header.kill();
+        let elem = ptr::read(&*this.data);
+
+        Header::release(ptr.cast());
+        elem
+    }
+
+    // Give up the data without waiting, the last child drops it
+    fn orphan(self) {
+        let ptr = self.ptr;
+        mem::forget(self);
+
+        let this = unsafe { &ptr.as_ref().header };
+        this.lock_children();
+        this.kill();
+        this.orphan.store(ptr.cast().as_ptr(), Ordering::SeqCst);
+
+        // The fence orders the orphan before checking the count as the last child orders its count
+        // before checking for the orphan, one of them sees the other
+        atomic::fence(Ordering::SeqCst);
+        if this.strong.load(Ordering::SeqCst) == 0 {
+            unsafe { Header::release_orphan(ptr.cast()) }
+        }
+    }
 }
 
 impl<T> AsRef<T> for ParentArc<T> {
@@ -297,170 +645,1118 @@ impl<T> ops::Deref for ParentArc<T> {
     }
 }
 
+impl<T: fmt::Debug> fmt::Debug for ParentArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ParentArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Send + Sync> Send for ParentArc<T> {}
+unsafe impl<T: Send + Sync> Sync for ParentArc<T> {}
+
 impl<T> Drop for ParentArc<T> {
     fn drop(&mut self) {
-        // Wait for all reads to be droped
-        let this = self.inner();
-        while this.strong.load(Ordering::Acquire) != 0 {}
+        // Wait for all reads to be droped, no reader can upgrade once the parent is dying
+        let this = self.header();
+        this.lock_children();
+        this.kill();
+        wait::until_released(&this.strong, &this.waker);
+
+        unsafe {
+            ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).data);
+            Header::release(self.ptr.cast());
+        }
     }
 }
 
-// Inner state shared by all instances: Parent, Weak, Child
-struct Womb<T> {
-    data: T,
-    lock: AtomicBool,
-    strong: AtomicUsize,
+/// Future returned by [`into_inner_async`](struct.ParentArc.html#method.into_inner_async).
+///
+/// It resolves to the underlying data once all [`ChildArc`](struct.ChildArc.html) have dropped.
+#[must_use = "futures do nothing unless polled or awaited"]
+pub struct IntoInner<T> {
+    parent: Option<ParentArc<T>>,
 }
 
-impl<T> Womb<T> {
-    fn as_nnptr(data: T) -> NonNull<Self> {
-        let x = Box::new(Self {
-            data,
-            lock: AtomicBool::new(false),
-            strong: AtomicUsize::new(0),
-        });
-        unsafe { NonNull::new_unchecked(Box::into_raw(x)) }
+impl<T> IntoInner<T> {
+    /// Reference to the underlying data while waiting for the children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future has already completed.
+    pub fn get_ref(&self) -> &T {
+        self.parent
+            .as_ref()
+            .expect("IntoInner polled after completion")
     }
 }
 
-/// Weak reference to a [`ParentArc`](struct.ParentArc.html).
-///
-/// This instance can be locked at any moment, you can try to upgrade it into a
-/// [`ChildArc`](struct.ChildArc.html) which assures it can be read until the reader is dropped.
+impl<T> Future for IntoInner<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let parent = self
+            .parent
+            .as_ref()
+            .expect("IntoInner polled after completion");
+        let this = parent.header();
+
+        // Register before checking so the last child cannot drop in between unnoticed
+        this.waker.register(cx.waker());
+        if this.strong.load(Ordering::SeqCst) != 0 {
+            return Poll::Pending;
+        }
+
+        let parent = self.parent.take().unwrap();
+        Poll::Ready(unsafe { parent.take() })
+    }
+}
+
+impl<T> Drop for IntoInner<T> {
+    fn drop(&mut self) {
+        // Never wait in a drop, the children may be held by the task dropping the future. The
+        // future is only built for data that can be dropped by a child on another thread
+        if let Some(parent) = self.parent.take() {
+            parent.orphan();
+        }
+    }
+}
+
+/// Shared owner of the data of a [`ParentArc`](struct.ParentArc.html).
 ///
-/// The typical way to obtain a Weak pointer is to call
-/// [`ParentArc::downgrade`](struct.ParentArc.html#method.downgrade).
-pub struct LockWeak<T> {
+/// Built by [`ParentArc::split`](struct.ParentArc.html#method.split), the co-parents read and
+/// downgrade the data like the parent but cannot lock the weak references or consume the data
+/// alone. Each co-parent gives up its share in [`into_parent`](#method.into_parent), the last one
+/// gets the parent back and decides what happens to the data. Dropping a co-parent gives up its
+/// share, the data is dropped with the last one.
+pub struct CoParent<T> {
     ptr: NonNull<Womb<T>>,
 }
 
-impl<T> LockWeak<T> {
-    /// Upgrades this Weak reference into a [`ChildArc`](struct.ChildArc.html) if the data is
-    /// unlocked or still owned by the [`ParentArc`](struct.ParentArc.html).
+impl<T> CoParent<T> {
+    // Build `n` co-parents of which the first takes the reference to the allocation of the caller
+    fn share(ptr: NonNull<Womb<T>>, n: usize) -> Vec<Self> {
+        let this = unsafe { ptr.as_ref() };
+        this.header.weak.fetch_add(n - 1, Ordering::Relaxed);
+        (0..n).map(|_| Self { ptr }).collect()
+    }
+
+    /// Split this share of the ownership between `n` co-parents, the data is then consumed once
+    /// they and the other co-parents all agree to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn split(self, n: usize) -> Vec<Self> {
+        assert!(n != 0, "cannot split a parent into zero co-parents");
+        let ptr = self.ptr;
+        mem::forget(self);
+
+        let this = unsafe { ptr.as_ref() };
+        this.header.parents.fetch_add(n - 1, Ordering::Relaxed);
+        Self::share(ptr, n)
+    }
+
+    /// Give up this share of the ownership, the last co-parent to do so gets the
+    /// [`ParentArc`](struct.ParentArc.html) back.
     ///
     /// # Examples
     /// ```rust
-    /// use parc::{ParentArc, LockWeak};
-    /// use std::sync::Mutex;
+    /// use parc::ParentArc;
     /// fn main() {
-    ///     let parent = ParentArc::new(Mutex::new(true));
+    ///     let mut co_parents = ParentArc::new(1).split(2);
     ///
-    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
-    ///     let child = weak.upgrade().unwrap();
+    ///     assert!(co_parents.pop().unwrap().into_parent().is_none());
+    ///     let parent = co_parents.pop().unwrap().into_parent().unwrap();
+    ///     assert_eq!(parent.block_into_inner(), 1);
     /// }
     /// ```
-    pub fn upgrade(&self) -> Option<ChildArc<T>> {
-        let this = self.inner()?;
+    pub fn into_parent(self) -> Option<ParentArc<T>> {
+        let ptr = self.ptr;
+        mem::forget(self);
 
-        if this.lock.load(Ordering::Relaxed) {
-            return None;
+        // Order the reads of the other co-parents before the last one takes the data
+        let this = unsafe { ptr.as_ref() };
+        if this.header.parents.fetch_sub(1, Ordering::AcqRel) == 1 {
+            Some(ParentArc { ptr })
+        } else {
+            unsafe { Header::release(ptr.cast()) };
+            None
         }
+    }
 
-        let mut n = this.strong.load(Ordering::Relaxed);
-        loop {
-            match this
-                .strong
-                .compare_exchange_weak(n, n + 1, Ordering::SeqCst, Ordering::Relaxed)
-            {
-                Ok(_) => break,
-                Err(old) => n = old,
-            }
-        }
-        Some(ChildArc::from(self.ptr))
+    /// Give up this share of the ownership, the last co-parent to do so blocks until all
+    /// [`ChildArc`](struct.ChildArc.html)s have dropped and returns the underlying data.
+    ///
+    /// See [`ParentArc::block_into_inner`](struct.ParentArc.html#method.block_into_inner).
+    pub fn block_into_inner(self) -> Option<T> {
+        self.into_parent().map(ParentArc::block_into_inner)
     }
 
-    // Pointer could be voided
-    fn inner(&self) -> Option<&Womb<T>> {
-        let address = self.ptr.as_ptr() as *mut () as usize;
-        if address == core::usize::MAX {
-            None
-        } else {
-            Some(unsafe { self.ptr.as_ref() })
+    /// Downgrade a co-parent into a [`LockWeak`](struct.LockWeak.html), see
+    /// [`ParentArc::downgrade`](struct.ParentArc.html#method.downgrade).
+    pub fn downgrade(other: &Self) -> LockWeak<T> {
+        other.header().retain();
+        LockWeak {
+            header: other.ptr.cast(),
+            data: Womb::data(other.ptr),
         }
     }
-}
 
-unsafe impl<T: Sync> Send for LockWeak<T> {}
-
-/// Unclonable owned reference to a [`ParentArc`](struct.ParentArc.html).
-///
-/// This type can be dereferenced into the underlying data.
-///
-/// # Examples
-/// ```rust
-/// use parc::{ParentArc, LockWeak, ChildArc};
-/// use std::sync::Mutex;
-/// fn main() {
-///     let parent = ParentArc::new(Mutex::new(true));
-///
-///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
-///     let child: ChildArc<_> = weak.upgrade().unwrap();
-///
-///     assert!(*child.lock().unwrap());
-/// }
-/// ```
-pub struct ChildArc<T> {
-    ptr: NonNull<Womb<T>>,
-}
+    /// Number of co-parents that have not given up their share yet.
+    pub fn co_parents(this: &Self) -> usize {
+        this.header().parents.load(Ordering::Relaxed)
+    }
 
-impl<T> ChildArc<T> {
-    fn from(ptr: NonNull<Womb<T>>) -> Self {
-        Self { ptr }
+    /// Check wether two co-parents own the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
     }
+
     fn inner(&self) -> &Womb<T> {
-        // safe because strong count is up one
-        unsafe { self.ptr.as_ref() }
+        unsafe { self.ptr.as_ref() } // Ok to do this because the data lives until the last share
+    }
+
+    fn header(&self) -> &Header {
+        &self.inner().header
     }
 }
 
-impl<T> AsRef<T> for ChildArc<T> {
+impl<T> AsRef<T> for CoParent<T> {
     fn as_ref(&self) -> &T {
         &self.inner().data
     }
 }
 
-impl<T> ops::Deref for ChildArc<T> {
+impl<T> ops::Deref for CoParent<T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         &self.inner().data
     }
 }
 
-impl<T> Drop for ChildArc<T> {
-    fn drop(&mut self) {
-        let strong = &self.inner().strong;
+impl<T: fmt::Debug> fmt::Debug for CoParent<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
 
-        let mut n = strong.load(Ordering::Relaxed);
-        loop {
-            match strong.compare_exchange_weak(n, n - 1, Ordering::SeqCst, Ordering::Relaxed) {
-                Ok(_) => break,
-                Err(old) => n = old,
-            }
-        }
+impl<T: fmt::Display> fmt::Display for CoParent<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
     }
 }
 
-#[cfg(all(test, not(feature = "no_std")))]
-mod tests {
-    extern crate std;
-    use super::*;
-    use std::sync;
-    use std::thread;
-    use std::vec::Vec;
+unsafe impl<T: Send + Sync> Send for CoParent<T> {}
+unsafe impl<T: Send + Sync> Sync for CoParent<T> {}
 
-    #[test]
-    fn new() {
-        let _ = ParentArc::new(2);
+impl<T> Drop for CoParent<T> {
+    fn drop(&mut self) {
+        // The last share drops the data like the parent
+        drop(CoParent { ptr: self.ptr }.into_parent());
     }
+}
 
-    #[test]
+// Inner state shared by all instances: Parent, Weak, Child
+//
+// The data is dropped or moved out by the parent, the allocation lives as long as a parent, weak
+// or child reference counted in `weak`. Weak and child references only point to the header and to
+// the part of the data they deref to, the header comes first so they can free the allocation.
+#[repr(C)]
+struct Womb<T> {
+    header: Header,
+    data: ManuallyDrop<T>,
+}
+
+impl<T> Womb<T> {
+    fn as_nnptr(data: T) -> NonNull<Self> {
+        let x = Box::new(Self {
+            header: Header {
+                lock: AtomicBool::new(false),
+                dead: AtomicBool::new(false),
+                parents: AtomicUsize::new(1),
+                strong: AtomicUsize::new(0),
+                weak: AtomicUsize::new(1),
+                waker: AtomicWaker::new(),
+                waiters: Waiters::new(),
+                orphan: AtomicPtr::new(ptr::null_mut()),
+                drop_data: Self::drop_data,
+                free: Self::free,
+            },
+            data: ManuallyDrop::new(data),
+        });
+        unsafe { NonNull::new_unchecked(Box::into_raw(x)) }
+    }
+
+    // Pointer to the data that does not read it, it may already be gone
+    fn data(ptr: NonNull<Self>) -> NonNull<T> {
+        unsafe { NonNull::new_unchecked(ptr::addr_of_mut!((*ptr.as_ptr()).data).cast()) }
+    }
+
+    unsafe fn drop_data(ptr: NonNull<Header>) {
+        ManuallyDrop::drop(&mut (*ptr.cast::<Self>().as_ptr()).data);
+    }
+
+    // The data has already been dropped or moved out
+    unsafe fn free(ptr: NonNull<Header>) {
+        drop(Box::from_raw(ptr.cast::<Self>().as_ptr()));
+    }
+}
+
+// Counters of a Womb, the same whatever the type of its data
+struct Header {
+    lock: AtomicBool,
+    dead: AtomicBool,
+    parents: AtomicUsize,
+    strong: AtomicUsize,
+    weak: AtomicUsize,
+    waker: AtomicWaker,
+    waiters: Waiters,
+    // Womb of a parent that gave up its data to its children, the last one drops it
+    orphan: AtomicPtr<Header>,
+    drop_data: unsafe fn(NonNull<Header>),
+    free: unsafe fn(NonNull<Header>),
+}
+
+impl Header {
+    // Returns the previous lock state, the fence orders the lock before the following count checks
+    // as the children order their count before checking the lock
+    fn lock_children(&self) -> bool {
+        let locked = self.lock.swap(true, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        locked
+    }
+
+    // The parent is going away, weak references waiting for an unlock fail from now on
+    fn kill(&self) {
+        self.dead.store(true, Ordering::SeqCst);
+        self.waiters.wake_all();
+    }
+
+    // Reason a child failed to upgrade while the weak references are locked
+    fn upgrade_error(&self) -> UpgradeError {
+        if self.dead.load(Ordering::SeqCst) {
+            UpgradeError::Dead
+        } else {
+            UpgradeError::Locked
+        }
+    }
+
+    fn release_strong(&self) {
+        match self.strong.fetch_sub(1, Ordering::SeqCst) {
+            // Last reader, wake a parent waiting in `into_inner_async` or parked in a blocking call
+            1 => self.released(),
+            // Last reader of a RwParentArc with a waiting writer, wake it
+            rw::LAST_READER => self.waiters.wake_all(),
+            _ => {}
+        }
+    }
+
+    // The strong count dropped to zero
+    fn released(&self) {
+        self.waker.wake();
+        atomic::fence(Ordering::SeqCst);
+        if !self.orphan.load(Ordering::SeqCst).is_null() {
+            // The releasing child holds a reference so the allocation outlives this call
+            unsafe { Header::release_orphan(NonNull::from(self)) }
+        }
+    }
+
+    // Drop the data of an orphan once, the parent and its last child may both try
+    unsafe fn release_orphan(ptr: NonNull<Self>) {
+        let orphan = ptr.as_ref().orphan.swap(ptr::null_mut(), Ordering::SeqCst);
+        if let Some(orphan) = NonNull::new(orphan) {
+            (orphan.as_ref().drop_data)(orphan);
+            Header::release(orphan);
+        }
+    }
+
+    fn retain(&self) {
+        self.weak.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Release a reference to the allocation, the last one frees it
+    unsafe fn release(ptr: NonNull<Self>) {
+        if ptr.as_ref().weak.fetch_sub(1, Ordering::Release) == 1 {
+            atomic::fence(Ordering::Acquire);
+            (ptr.as_ref().free)(ptr);
+        }
+    }
+}
+
+// Tasks waiting in `upgrade_async` for the parent to unlock or die, kept in a lock-free stack that
+// is drained at once to wake them
+struct Waiters {
+    head: AtomicPtr<Node>,
+    // Bumped by every drain, a registration that took the stack out checks it to not lose a wake
+    wakes: AtomicUsize,
+}
+
+// The waiters of dropped futures are pruned by the next registration
+struct Node {
+    waiter: Weak<Waiter>,
+    next: *mut Node,
+}
+
+// Registration of an `Upgrade` future, it is in the stack at most once whatever the number of polls
+struct Waiter {
+    waker: AtomicWaker,
+    queued: AtomicBool,
+}
+
+impl Waiter {
+    fn new() -> Self {
+        Self {
+            waker: AtomicWaker::new(),
+            queued: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Waiters {
+    fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            wakes: AtomicUsize::new(0),
+        }
+    }
+
+    // A waiter still in the stack only gets its waker replaced, the drain wakes the last one
+    fn register(&self, waiter: &Arc<Waiter>, waker: &Waker) {
+        waiter.waker.register(waker);
+        if waiter.queued.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let first = Box::into_raw(Box::new(Node {
+            waiter: Arc::downgrade(waiter),
+            next: ptr::null_mut(),
+        }));
+
+        // Take the stack out to free the nodes of the dropped futures, they would pile up for as
+        // long as the parent stays locked otherwise
+        let wakes = self.wakes.load(Ordering::SeqCst);
+        let mut last = first;
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::SeqCst);
+        while !node.is_null() {
+            let next = unsafe { (*node).next };
+            if unsafe { (*node).waiter.strong_count() } == 0 {
+                drop(unsafe { Box::from_raw(node) });
+            } else {
+                unsafe { (*last).next = node };
+                last = node;
+            }
+            node = next;
+        }
+
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*last).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, first, Ordering::SeqCst, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+
+        // A drain while the stack was out missed the waiters put back, they are woken now
+        if self.wakes.load(Ordering::SeqCst) != wakes {
+            self.wake_all();
+        }
+    }
+
+    // Called after the lock state changed, the fence orders the change before taking the stack as
+    // the waiters order their push before checking the lock
+    fn wake_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+        for waiter in self.drain() {
+            // Out of the stack before the wake so the woken task registers again if it waits
+            waiter.queued.store(false, Ordering::SeqCst);
+            waiter.waker.wake();
+        }
+    }
+
+    fn drain(&self) -> impl Iterator<Item = Arc<Waiter>> {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::SeqCst);
+        core::iter::from_fn(move || {
+            while !node.is_null() {
+                let current = unsafe { Box::from_raw(node) };
+                node = current.next;
+                if let Some(waiter) = current.waiter.upgrade() {
+                    return Some(waiter);
+                }
+            }
+            None
+        })
+    }
+
+    #[cfg(all(test, not(loom)))]
+    fn len(&self) -> usize {
+        let mut node = self.head.load(Ordering::SeqCst);
+        let mut len = 0;
+        while !node.is_null() {
+            node = unsafe { (*node).next };
+            len += 1;
+        }
+        len
+    }
+}
+
+impl Drop for Waiters {
+    fn drop(&mut self) {
+        self.drain().for_each(drop);
+    }
+}
+
+/// Weak reference to a [`ParentArc`](struct.ParentArc.html).
+///
+/// This instance can be locked at any moment, you can try to upgrade it into a
+/// [`ChildArc`](struct.ChildArc.html) which assures it can be read until the reader is dropped.
+///
+/// The typical way to obtain a Weak pointer is to call
+/// [`ParentArc::downgrade`](struct.ParentArc.html#method.downgrade).
+pub struct LockWeak<T: ?Sized> {
+    header: NonNull<Header>,
+    data: NonNull<T>,
+}
+
+impl<T: ?Sized> LockWeak<T> {
+    /// Upgrades this Weak reference into a [`ChildArc`](struct.ChildArc.html) if the data is
+    /// unlocked and still owned by the [`ParentArc`](struct.ParentArc.html).
+    ///
+    /// Fails with [`Locked`](enum.UpgradeError.html#variant.Locked) while the parent is locked and
+    /// with [`Dead`](enum.UpgradeError.html#variant.Dead) once it is consumed or dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak, UpgradeError};
+    /// use std::sync::Mutex;
+    /// fn main() {
+    ///     let parent = ParentArc::new(Mutex::new(true));
+    ///
+    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+    ///     let child = weak.upgrade().unwrap();
+    ///     drop(child);
+    ///
+    ///     parent.lock();
+    ///     assert_eq!(weak.upgrade().unwrap_err(), UpgradeError::Locked);
+    ///
+    ///     drop(parent);
+    ///     assert_eq!(weak.upgrade().unwrap_err(), UpgradeError::Dead);
+    /// }
+    /// ```
+    pub fn upgrade(&self) -> Result<ChildArc<T>, UpgradeError> {
+        let this = self.header();
+
+        // Count the child before checking the lock, a parent locking at the same time either
+        // waits for it or makes it back off
+        this.strong.fetch_add(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+        if this.lock.load(Ordering::SeqCst) {
+            this.release_strong();
+            return Err(this.upgrade_error());
+        }
+        Ok(ChildArc::from(self.header, self.data))
+    }
+
+    /// Asynchronous version of [`upgrade`](#method.upgrade), the returned future waits for the
+    /// parent to unlock and only fails once the parent is consumed or dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak, UpgradeError};
+    /// use std::sync::Mutex;
+    /// fn main() {
+    ///     let parent = ParentArc::new(Mutex::new(0));
+    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+    ///
+    ///     parent.lock();
+    ///     let handle = std::thread::spawn(move || {
+    ///         let child = futures::executor::block_on(weak.upgrade_async()).unwrap();
+    ///         *child.lock().unwrap() += 1;
+    ///     });
+    ///
+    ///     std::thread::sleep(std::time::Duration::from_millis(10));
+    ///     parent.unlock();
+    ///     handle.join().unwrap();
+    ///     assert_eq!(parent.block_into_inner().into_inner().unwrap(), 1);
+    /// }
+    /// ```
+    pub fn upgrade_async(&self) -> Upgrade<'_, T> {
+        Upgrade {
+            weak: self,
+            waiter: None,
+        }
+    }
+
+    /// Make a weak reference to a part of the data, its children share the strong count of this
+    /// reference so the parent still locks them and waits for them.
+    ///
+    /// The part is found through a temporary child, this fails like [`upgrade`](#method.upgrade).
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// fn main() {
+    ///     let parent = ParentArc::new((AtomicUsize::new(0), String::from("name")));
+    ///
+    ///     let count: LockWeak<AtomicUsize> = ParentArc::downgrade(&parent).map(|t| &t.0).unwrap();
+    ///     let name: LockWeak<str> = ParentArc::downgrade(&parent).map(|t| t.1.as_str()).unwrap();
+    ///
+    ///     std::thread::spawn(move || count.upgrade().unwrap().fetch_add(1, Ordering::SeqCst))
+    ///         .join()
+    ///         .unwrap();
+    ///     assert_eq!(&*name.upgrade().unwrap(), "name");
+    ///
+    ///     let (count, _) = parent.block_into_inner();
+    ///     assert_eq!(count.into_inner(), 1);
+    /// }
+    /// ```
+    pub fn map<U, F>(&self, f: F) -> Result<LockWeak<U>, UpgradeError>
+    where
+        U: ?Sized,
+        F: FnOnce(&T) -> &U,
+    {
+        // The data is never moved or mutably borrowed while the parent owns it, the part stays
+        // where the temporary child found it
+        let child = ChildArc::map(self.upgrade()?, f);
+        child.header().retain();
+        Ok(LockWeak {
+            header: child.header,
+            data: child.data,
+        })
+    }
+
+    /// Check wether two weak references point to the same part of the same allocation.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, LockWeak};
+    /// fn main() {
+    ///     let parent = ParentArc::new(0);
+    ///
+    ///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+    ///     assert!(LockWeak::ptr_eq(&weak, &weak.clone()));
+    ///     assert!(!LockWeak::ptr_eq(&weak, &ParentArc::downgrade(&ParentArc::new(0))));
+    /// }
+    /// ```
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::eq(this.data.as_ptr(), other.data.as_ptr())
+    }
+
+    fn header(&self) -> &Header {
+        // safe because the allocation is kept alive by this reference
+        unsafe { self.header.as_ref() }
+    }
+}
+
+impl<T: ?Sized> Clone for LockWeak<T> {
+    fn clone(&self) -> Self {
+        self.header().retain();
+        Self {
+            header: self.header,
+            data: self.data,
+        }
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for LockWeak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(LockWeak)")
+    }
+}
+
+/// Weak references are equal if they point to the same part of the same allocation.
+impl<T: ?Sized> PartialEq for LockWeak<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Self::ptr_eq(self, other)
+    }
+}
+
+impl<T: ?Sized> Eq for LockWeak<T> {}
+
+impl<T: ?Sized> Hash for LockWeak<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(self.data.as_ptr(), state)
+    }
+}
+
+impl<T: ?Sized> Drop for LockWeak<T> {
+    fn drop(&mut self) {
+        unsafe { Header::release(self.header) }
+    }
+}
+
+unsafe impl<T: ?Sized + Sync> Send for LockWeak<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for LockWeak<T> {}
+
+/// Future returned by [`upgrade_async`](struct.LockWeak.html#method.upgrade_async).
+#[must_use = "futures do nothing unless polled or awaited"]
+pub struct Upgrade<'a, T: ?Sized> {
+    weak: &'a LockWeak<T>,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<T: ?Sized> Future for Upgrade<'_, T> {
+    type Output = Result<ChildArc<T>, UpgradeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.weak.upgrade() {
+            Err(UpgradeError::Locked) => {}
+            res => return Poll::Ready(res),
+        }
+
+        // Register before checking again so an unlock cannot happen in between unnoticed
+        let weak = self.weak;
+        let waiter = self.waiter.get_or_insert_with(|| Arc::new(Waiter::new()));
+        weak.header().waiters.register(waiter, cx.waker());
+        match self.weak.upgrade() {
+            Err(UpgradeError::Locked) => Poll::Pending,
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+/// Unclonable owned reference to a [`ParentArc`](struct.ParentArc.html).
+///
+/// This type can be dereferenced into the underlying data.
+///
+/// # Examples
+/// ```rust
+/// use parc::{ParentArc, LockWeak, ChildArc};
+/// use std::sync::Mutex;
+/// fn main() {
+///     let parent = ParentArc::new(Mutex::new(true));
+///
+///     let weak: LockWeak<_> = ParentArc::downgrade(&parent);
+///     let child: ChildArc<_> = weak.upgrade().unwrap();
+///
+///     assert!(*child.lock().unwrap());
+/// }
+/// ```
+pub struct ChildArc<T: ?Sized> {
+    header: NonNull<Header>,
+    data: NonNull<T>,
+}
+
+impl<T: ?Sized> ChildArc<T> {
+    // The strong count has been taken by the caller
+    fn from(header: NonNull<Header>, data: NonNull<T>) -> Self {
+        unsafe { header.as_ref() }.retain();
+        Self { header, data }
+    }
+
+    /// Make a child that derefs to a part of the data, like
+    /// [`Ref::map`](https://doc.rust-lang.org/std/cell/struct.Ref.html#method.map). It keeps the
+    /// strong count of `this` so the parent waits for it to drop.
+    ///
+    /// This is an associated function so it does not shadow a `map` method of the data.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{ParentArc, ChildArc};
+    /// fn main() {
+    ///     let parent = ParentArc::new((1, 2));
+    ///
+    ///     let child = ParentArc::downgrade(&parent).upgrade().unwrap();
+    ///     let second: ChildArc<i32> = ChildArc::map(child, |t| &t.1);
+    ///     assert_eq!(*second, 2);
+    /// }
+    /// ```
+    pub fn map<U, F>(this: Self, f: F) -> ChildArc<U>
+    where
+        U: ?Sized,
+        F: FnOnce(&T) -> &U,
+    {
+        let data = NonNull::from(f(&this));
+        let this = ManuallyDrop::new(this);
+        ChildArc {
+            header: this.header,
+            data,
+        }
+    }
+
+    /// Check wether two children read the same part of the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::eq(this.data.as_ptr(), other.data.as_ptr())
+    }
+
+    fn header(&self) -> &Header {
+        // safe because strong count is up one
+        unsafe { self.header.as_ref() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: ?Sized + Sync> Send for ChildArc<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for ChildArc<T> {}
+
+impl<T: ?Sized> AsRef<T> for ChildArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> ops::Deref for ChildArc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // safe because the parent waits for this child before it drops or moves the data
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<T: ?Sized> Drop for ChildArc<T> {
+    fn drop(&mut self) {
+        self.header().release_strong();
+        unsafe { Header::release(self.header) }
+    }
+}
+
+// The tests also run under Miri with `cargo +nightly miri test -p parc`, so they must not rely on
+// how long a `thread::sleep` lasts
+#[cfg(all(test, not(loom)))]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::sync;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn new() {
+        let _ = ParentArc::new(2);
+    }
+
+    // Polls the future once, signals that it waits and drives it to completion
+    fn block_on_waiting<F: Future + Unpin>(
+        mut fut: F,
+        waiting: sync::mpsc::Sender<()>,
+    ) -> F::Output {
+        let waker = futures::task::noop_waker();
+        assert!(Pin::new(&mut fut)
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        waiting.send(()).unwrap();
+        futures::executor::block_on(fut)
+    }
+
+    // Returns once the parent of the weak reference locked its children
+    fn until_locked<T: ?Sized>(weak: &LockWeak<T>) {
+        while weak.upgrade().is_ok() {
+            thread::yield_now();
+        }
+    }
+
+    // Counts the drops of the value
+    struct Tracked<'a>(&'a sync::atomic::AtomicUsize);
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn drop_data_with_parent() {
+        let drops = sync::atomic::AtomicUsize::new(0);
+
+        let m = ParentArc::new(Tracked(&drops));
+        let weak = ParentArc::downgrade(&m);
+        drop(weak.upgrade().unwrap());
+        drop(m);
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 1);
+
+        // The weak reference outlives the parent
+        assert_eq!(weak.upgrade().err(), Some(UpgradeError::Dead));
+        drop(weak);
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn take_data_once() {
+        let drops = sync::atomic::AtomicUsize::new(0);
+
+        let m = ParentArc::new(Tracked(&drops));
+        let weak = ParentArc::downgrade(&m);
+        let data = m.block_into_inner();
+        assert_eq!(weak.upgrade().err(), Some(UpgradeError::Dead));
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 0);
+
+        drop(data);
+        drop(weak);
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn child_outlives_weak() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+        let weak = ParentArc::downgrade(&m);
+        let child = weak.upgrade().unwrap();
+        drop(weak);
+
+        *child.lock().unwrap() += 1;
+        drop(child);
+        assert_eq!(*m.block_into_inner().lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn cloned_weak_threads() {
+        let m = ParentArc::new(sync::atomic::AtomicUsize::new(0));
+        let weak = ParentArc::downgrade(&m);
+
+        let vh: Vec<_> = (0..4)
+            .map(|_| {
+                let weak = weak.clone();
+                thread::spawn(move || {
+                    if let Ok(at) = weak.upgrade() {
+                        at.fetch_add(1, sync::atomic::Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for h in vh {
+            h.join().unwrap();
+        }
+
+        assert_eq!(weak, ParentArc::downgrade(&m));
+        assert_eq!(m.block_into_inner().into_inner(), 4);
+    }
+
+    #[test]
+    fn send_child() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+        let child = ParentArc::downgrade(&m).upgrade().unwrap();
+        assert!(ChildArc::ptr_eq(
+            &child,
+            &ParentArc::downgrade(&m).upgrade().unwrap()
+        ));
+
+        thread::spawn(move || *child.lock().unwrap() += 1)
+            .join()
+            .unwrap();
+        assert_eq!(
+            std::format!("{:?}", m),
+            "Mutex { data: 1, poisoned: false, .. }"
+        );
+    }
+
+    #[test]
+    fn hash_weak() {
+        let (a, b) = (ParentArc::new(0), ParentArc::new(0));
+
+        let mut set = std::collections::HashSet::new();
+        set.insert(ParentArc::downgrade(&a));
+        set.insert(ParentArc::downgrade(&a));
+        set.insert(ParentArc::downgrade(&b));
+        assert_eq!(set.len(), 2);
+        assert!(!ParentArc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn async_upgrade_after_unlock() {
+        let m = ParentArc::new(sync::atomic::AtomicUsize::new(0));
+        m.lock();
+
+        let (tx, rx) = sync::mpsc::channel();
+        let vh: Vec<_> = (0..4)
+            .map(|_| {
+                let (weak, tx) = (ParentArc::downgrade(&m), tx.clone());
+                thread::spawn(move || {
+                    let child = block_on_waiting(weak.upgrade_async(), tx).unwrap();
+                    child.fetch_add(1, sync::atomic::Ordering::SeqCst);
+                })
+            })
+            .collect();
+
+        for _ in 0..4 {
+            rx.recv().unwrap();
+        }
+        m.unlock();
+        for h in vh {
+            h.join().unwrap();
+        }
+        assert_eq!(m.block_into_inner().into_inner(), 4);
+    }
+
+    #[test]
+    fn async_upgrade_dead_parent() {
+        let m = ParentArc::new(0);
+        let weak = ParentArc::downgrade(&m);
+        m.lock();
+
+        let (tx, rx) = sync::mpsc::channel();
+        let h = thread::spawn(move || block_on_waiting(weak.upgrade_async(), tx).err());
+
+        rx.recv().unwrap();
+        assert_eq!(m.block_into_inner(), 0);
+        assert_eq!(h.join().unwrap(), Some(UpgradeError::Dead));
+    }
+
+    #[test]
+    fn async_upgrade_registers_once() {
+        let m = ParentArc::new(0);
+        let weak = ParentArc::downgrade(&m);
+        m.lock();
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut upgrade = weak.upgrade_async();
+        for _ in 0..100 {
+            assert!(Pin::new(&mut upgrade).poll(&mut cx).is_pending());
+        }
+        assert_eq!(m.header().waiters.len(), 1);
+
+        m.unlock();
+        assert_eq!(m.header().waiters.len(), 0);
+        assert!(matches!(
+            Pin::new(&mut upgrade).poll(&mut cx),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn dropped_upgrades_leave_the_waiters() {
+        let m = ParentArc::new(0);
+        let weak = ParentArc::downgrade(&m);
+        m.lock();
+
+        // Retrying with a fresh future every time, the previous waiter is pruned on registration
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..100 {
+            let mut upgrade = weak.upgrade_async();
+            assert!(Pin::new(&mut upgrade).poll(&mut cx).is_pending());
+        }
+        assert_eq!(m.header().waiters.len(), 1);
+
+        let mut upgrade = weak.upgrade_async();
+        assert!(Pin::new(&mut upgrade).poll(&mut cx).is_pending());
+        assert_eq!(m.header().waiters.len(), 1);
+
+        m.unlock();
+        assert_eq!(m.header().waiters.len(), 0);
+        assert!(matches!(
+            Pin::new(&mut upgrade).poll(&mut cx),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn map_weak_fields() {
+        struct Pair {
+            reads: sync::atomic::AtomicUsize,
+            writes: sync::Mutex<usize>,
+        }
+        let m = ParentArc::new(Pair {
+            reads: sync::atomic::AtomicUsize::new(0),
+            writes: sync::Mutex::new(0),
+        });
+
+        let reads = ParentArc::downgrade(&m).map(|p| &p.reads).unwrap();
+        let writes = ParentArc::downgrade(&m).map(|p| &p.writes).unwrap();
+        let (tx, rx) = sync::mpsc::channel();
+        let vh: Vec<_> = (0..4)
+            .map(|i| {
+                let (reads, writes) = (reads.clone(), writes.clone());
+                let mut started = Some(tx.clone());
+                thread::spawn(move || match i % 2 {
+                    0 => {
+                        while let Ok(at) = reads.upgrade() {
+                            at.fetch_add(1, sync::atomic::Ordering::SeqCst);
+                            if let Some(tx) = started.take() {
+                                tx.send(()).unwrap();
+                            }
+                        }
+                    }
+                    _ => {
+                        while let Ok(mutex) = writes.upgrade() {
+                            *mutex.lock().unwrap() += 1;
+                            if let Some(tx) = started.take() {
+                                tx.send(()).unwrap();
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..4 {
+            rx.recv().unwrap();
+        }
+        let child = reads.upgrade().unwrap();
+        assert!(LockWeak::ptr_eq(&reads, &reads.clone()));
+        let parent = thread::spawn(move || m.block_into_inner());
+        until_locked(&reads);
+        drop(child);
+        for h in vh {
+            h.join().unwrap();
+        }
+        let pair = parent.join().unwrap();
+        assert!(pair.reads.into_inner() > 0 && pair.writes.into_inner().unwrap() > 0);
+        assert_eq!(reads.upgrade().err(), Some(UpgradeError::Dead));
+        assert_eq!(writes.map(|w| w).err(), Some(UpgradeError::Dead));
+    }
+
+    #[test]
+    fn map_child_outlives_weak() {
+        static DROPS: sync::atomic::AtomicUsize = sync::atomic::AtomicUsize::new(0);
+
+        let m = ParentArc::new((Tracked(&DROPS), [1, 2, 3]));
+        let weak = ParentArc::downgrade(&m);
+        let tail = ChildArc::map(weak.upgrade().unwrap(), |t| &t.1[1..]);
+        let probe = weak.map(|t| &t.1).unwrap();
+        drop(weak);
+
+        let h = thread::spawn(move || drop(m));
+        until_locked(&probe);
+        assert_eq!(DROPS.load(sync::atomic::Ordering::SeqCst), 0);
+        assert_eq!(*tail, [2, 3]);
+
+        drop(tail);
+        h.join().unwrap();
+        assert_eq!(DROPS.load(sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn lock_twice() {
+        let m = ParentArc::new(0);
+        m.lock();
+        m.lock();
+        assert!(m.is_locked());
+        m.unlock();
+        m.unlock();
+        assert!(!m.is_locked());
+    }
+
+    #[test]
     fn one_simple_thread() {
         let m = ParentArc::new(sync::Mutex::new(0));
         let _ = thread::spawn({
             let weak = ParentArc::downgrade(&m);
-            move || match weak.upgrade() {
-                Some(mutex) => *mutex.lock().unwrap() += 1,
-                None => {}
+            move || {
+                if let Ok(mutex) = weak.upgrade() {
+                    *mutex.lock().unwrap() += 1;
+                }
             }
         })
         .join();
@@ -472,9 +1768,10 @@ mod tests {
         let m = ParentArc::new(sync::Mutex::new(0));
         let h = thread::spawn({
             let weak = ParentArc::downgrade(&m);
-            move || match weak.upgrade() {
-                Some(mutex) => *mutex.lock().unwrap() += 1,
-                None => {}
+            move || {
+                if let Ok(mutex) = weak.upgrade() {
+                    *mutex.lock().unwrap() += 1;
+                }
             }
         });
         let _: sync::Mutex<usize> = m.block_into_inner();
@@ -487,9 +1784,10 @@ mod tests {
         for _ in 0..10 {
             let _ = thread::spawn({
                 let weak = ParentArc::downgrade(&m);
-                move || match weak.upgrade() {
-                    Some(mutex) => *mutex.lock().unwrap() += 1,
-                    None => {}
+                move || {
+                    if let Ok(mutex) = weak.upgrade() {
+                        *mutex.lock().unwrap() += 1;
+                    }
                 }
             })
             .join();
@@ -502,10 +1800,9 @@ mod tests {
         let m = ParentArc::new(sync::Mutex::new(0));
         let h = thread::spawn({
             let weak = ParentArc::downgrade(&m);
-            move || loop {
-                match weak.upgrade() {
-                    Some(mutex) => *mutex.lock().unwrap() += 1,
-                    None => break,
+            move || {
+                while let Ok(mutex) = weak.upgrade() {
+                    *mutex.lock().unwrap() += 1;
                 }
             }
         });
@@ -521,10 +1818,9 @@ mod tests {
         for _ in 0..10 {
             let h = thread::spawn({
                 let weak = ParentArc::downgrade(&m);
-                move || loop {
-                    match weak.upgrade() {
-                        Some(mutex) => *mutex.lock().unwrap() += 1,
-                        None => break,
+                move || {
+                    while let Ok(mutex) = weak.upgrade() {
+                        *mutex.lock().unwrap() += 1;
                     }
                 }
             });
@@ -537,33 +1833,239 @@ mod tests {
         }
     }
 
+    #[test]
+    fn handoff_loop_read_threads() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+
+        let (tx, rx) = sync::mpsc::channel();
+        let mut vh = Vec::new();
+        for _ in 0..10 {
+            let h = thread::spawn({
+                let weak = ParentArc::downgrade(&m);
+                let mut started = Some(tx.clone());
+                move || {
+                    while let Ok(mutex) = weak.upgrade() {
+                        *mutex.lock().unwrap() += 1;
+                        if let Some(tx) = started.take() {
+                            tx.send(()).unwrap();
+                        }
+                    }
+                }
+            });
+            vh.push(h);
+        }
+
+        // The readers stay attached to the parent on its new thread
+        let owner = thread::spawn(move || {
+            for _ in 0..10 {
+                rx.recv().unwrap();
+            }
+            m.block_into_inner()
+        });
+        for h in vh {
+            let _ = h.join();
+        }
+        assert!(owner.join().unwrap().into_inner().unwrap() > 0);
+    }
+
+    #[test]
+    fn split_loop_read_threads() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+
+        let mut vh = Vec::new();
+        for _ in 0..10 {
+            let h = thread::spawn({
+                let weak = ParentArc::downgrade(&m);
+                move || {
+                    while let Ok(mutex) = weak.upgrade() {
+                        *mutex.lock().unwrap() += 1;
+                    }
+                }
+            });
+            vh.push(h);
+        }
+
+        let co_parents = m.split(3);
+        assert_eq!(CoParent::co_parents(&co_parents[0]), 3);
+        assert!(CoParent::ptr_eq(&co_parents[0], &co_parents[2]));
+
+        // Only the last co-parent to agree consumes the data, the others keep reading it
+        let owners: Vec<_> = co_parents
+            .into_iter()
+            .map(|co_parent| {
+                thread::spawn(move || {
+                    let weak = CoParent::downgrade(&co_parent);
+                    assert!(weak.upgrade().is_ok());
+                    co_parent.block_into_inner()
+                })
+            })
+            .collect();
+        let inner: Vec<_> = owners
+            .into_iter()
+            .filter_map(|h| h.join().unwrap())
+            .collect();
+        for h in vh {
+            let _ = h.join();
+        }
+        assert_eq!(inner.len(), 1);
+    }
+
+    #[test]
+    fn split_drop_last() {
+        let drops = sync::atomic::AtomicUsize::new(0);
+
+        let mut co_parents = ParentArc::new(Tracked(&drops)).split(2);
+        let nested = co_parents.pop().unwrap().split(2);
+        let weak = CoParent::downgrade(&nested[0]);
+        assert_eq!(CoParent::co_parents(&co_parents[0]), 3);
+
+        drop(co_parents);
+        drop(nested);
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(weak.upgrade().err(), Some(UpgradeError::Dead));
+    }
+
+    #[test]
+    fn async_into_inner() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+
+        let mut vh = Vec::new();
+        for _ in 0..10 {
+            let h = thread::spawn({
+                let weak = ParentArc::downgrade(&m);
+                move || {
+                    while let Ok(mutex) = weak.upgrade() {
+                        *mutex.lock().unwrap() += 1;
+                    }
+                }
+            });
+            vh.push(h);
+        }
+
+        let _: sync::Mutex<usize> = futures::executor::block_on(m.into_inner_async());
+        for h in vh {
+            let _ = h.join();
+        }
+    }
+
+    #[test]
+    fn async_into_inner_same_thread() {
+        use core::task::{Context, Poll};
+        use futures::task::noop_waker;
+
+        let m = ParentArc::new(0);
+        let child = ParentArc::downgrade(&m).upgrade().unwrap();
+
+        // Polling does not block while the child is alive on this thread
+        let mut fut = m.into_inner_async();
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut fut).poll(&mut cx).is_pending());
+        assert_eq!(*fut.get_ref(), 0);
+
+        drop(child);
+        assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Ready(0));
+    }
+
+    #[test]
+    fn drop_pending_into_inner() {
+        let drops = sync::atomic::AtomicUsize::new(0);
+
+        let m = ParentArc::new(Tracked(&drops));
+        let weak = ParentArc::downgrade(&m);
+        let child = weak.upgrade().unwrap();
+
+        // Dropping the future does not wait for the child held by this thread
+        drop(m.into_inner_async());
+        assert_eq!(weak.upgrade().err(), Some(UpgradeError::Dead));
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 0);
+
+        drop(child);
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 1);
+        drop(weak);
+
+        // Without children the data is dropped with the future
+        drop(ParentArc::new(Tracked(&drops)).into_inner_async());
+        assert_eq!(drops.load(sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn timeout_into_inner() {
+        let m = ParentArc::new(sync::Mutex::new(0));
+        let weak = ParentArc::downgrade(&m);
+
+        let (tx, rx) = sync::mpsc::channel();
+        let (release, released) = sync::mpsc::channel();
+        let h = thread::spawn(move || {
+            let child = weak.upgrade().unwrap();
+            tx.send(()).unwrap();
+            released.recv().unwrap();
+            drop(child);
+        });
+        rx.recv().unwrap();
+
+        // The child outlives the first deadline
+        let m = match m.into_inner_timeout(std::time::Duration::from_millis(1)) {
+            Err(TryUnwrapError::WouldBlock(m)) => m,
+            _ => unreachable!(),
+        };
+        assert!(!m.is_locked());
+        release.send(()).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let _: sync::Mutex<usize> = m.into_inner_deadline(deadline).unwrap();
+        h.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn timeout_keeps_caller_lock() {
+        let m = ParentArc::new(0);
+        let weak = ParentArc::downgrade(&m);
+        let child = weak.upgrade().unwrap();
+
+        m.lock();
+        let m = match m.into_inner_timeout(std::time::Duration::from_millis(1)) {
+            Err(TryUnwrapError::WouldBlock(m)) => m,
+            _ => unreachable!(),
+        };
+        assert!(m.is_locked());
+        assert_eq!(weak.upgrade().err(), Some(UpgradeError::Locked));
+
+        drop(child);
+        m.unlock();
+        assert_eq!(*weak.upgrade().unwrap(), 0);
+    }
+
     #[test]
     #[should_panic]
     fn one_panic_read_threads() {
         let m = ParentArc::new(sync::atomic::AtomicUsize::new(0));
 
+        let (tx, rx) = sync::mpsc::channel();
+
         let mut vh = Vec::new();
         for i in 0..10 {
             let h = thread::spawn({
                 let weak = ParentArc::downgrade(&m);
-                move || loop {
-                    match weak.upgrade() {
-                        Some(at) => {
-                            if i != 1 {
-                                at.store(1, sync::atomic::Ordering::SeqCst);
-                            } else {
-                                panic!()
-                            }
+                let tx = tx.clone();
+                move || {
+                    while let Ok(at) = weak.upgrade() {
+                        if i != 1 {
+                            at.store(1, sync::atomic::Ordering::SeqCst);
+                        } else {
+                            tx.send(()).unwrap();
+                            panic!()
                         }
-                        None => break,
                     }
                 }
             });
             vh.push(h);
         }
 
-        //wait for all threads to launch
-        thread::sleep(std::time::Duration::new(0, 100));
+        // wait for the panicking thread to hold a child
+        rx.recv().unwrap();
 
         let _: sync::atomic::AtomicUsize = m.block_into_inner();
 
@@ -0,0 +1,647 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::future::Future;
+use core::hash::{Hash, Hasher};
+use core::ops;
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::task::{Context, Poll};
+
+use super::{
+    atomic, wait, ChildArc, Header, IntoInner, LockWeak, Ordering, ParentArc, UpgradeError, Womb,
+};
+
+// Set on the strong count while a writer holds or waits for the data, the other bits count readers
+const WRITER: usize = !(usize::MAX >> 1);
+
+// Strong count before the last reader drops while a writer waits for it
+pub(super) const LAST_READER: usize = WRITER + 1;
+
+// Data of a RwParentArc, only accessed through the protocol on the strong count
+struct RwData<T>(UnsafeCell<T>);
+
+unsafe impl<T: Send + Sync> Sync for RwData<T> {}
+
+impl<T> RwData<T> {
+    fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl Header {
+    // Count a reader, returns false if it had to back off because of a writer
+    fn acquire_read(&self) -> bool {
+        if self.strong.fetch_add(1, Ordering::SeqCst) & WRITER != 0 {
+            self.release_strong();
+            return false;
+        }
+        true
+    }
+
+    // Returns false if another writer already holds the bit
+    fn acquire_write(&self) -> bool {
+        self.strong.fetch_or(WRITER, Ordering::SeqCst) & WRITER == 0
+    }
+
+    fn release_write(&self) {
+        // Last child, wake a parent waiting on it
+        if self.strong.fetch_sub(WRITER, Ordering::SeqCst) == WRITER {
+            self.released();
+        }
+        // Wake the readers and writers waiting for this one
+        self.waiters.wake_all();
+    }
+
+    // Readers are counted while the writer bit is set, wait for them to drop
+    fn until_readers_released(&self) {
+        wait::until(&self.waiters, || {
+            self.strong.load(Ordering::SeqCst) == WRITER
+        });
+    }
+
+    // Waits without touching the count, the readers backing off would keep the writer waiting
+    fn until_writer_released(&self) {
+        wait::until(&self.waiters, || {
+            self.strong.load(Ordering::SeqCst) & WRITER == 0
+        });
+    }
+}
+
+/// Owner of data shared between readers and writers.
+///
+/// Like a [`ParentArc`](struct.ParentArc.html) it locks its weak references and waits for its
+/// children to drop before it can be consumed. Its [`RwLockWeak`](struct.RwLockWeak.html) can
+/// upgrade either into a shared [`ReadChildArc`](struct.ReadChildArc.html) or into an exclusive
+/// [`WriteChildArc`](struct.WriteChildArc.html), no `Mutex` or `RwLock` is needed around the data.
+///
+/// A writer waits for the readers alive when it upgraded to drop and new readers wait for it, the
+/// waiting thread parks under `std` and spins without it. Readers and writers held by the same thread deadlock like they would on a `RwLock`.
+///
+/// # Examples
+/// ```rust
+/// use parc::{RwParentArc, RwLockWeak};
+/// fn main() {
+///     let parent = RwParentArc::new(0);
+///     let weak: RwLockWeak<_> = RwParentArc::downgrade(&parent);
+///
+///     let handle = std::thread::spawn(move || {
+///         if let Ok(mut writer) = weak.write() {
+///             *writer += 1;
+///         }
+///     });
+///     handle.join().unwrap();
+///
+///     assert_eq!(*parent.read(), 1);
+///     assert_eq!(parent.block_into_inner(), 1);
+/// }
+/// ```
+pub struct RwParentArc<T> {
+    parent: ParentArc<RwData<T>>,
+}
+
+impl<T> RwParentArc<T> {
+    /// Build a new [`RwParentArc`](struct.RwParentArc.html)
+    pub fn new(data: T) -> Self {
+        Self {
+            parent: ParentArc::new(RwData(UnsafeCell::new(data))),
+        }
+    }
+
+    /// Locks all [`RwLockWeak`](struct.RwLockWeak.html) of this instance, see
+    /// [`ParentArc::lock`](struct.ParentArc.html#method.lock).
+    pub fn lock(&self) {
+        self.parent.lock()
+    }
+
+    /// Check wether the [`RwLockWeak`](struct.RwLockWeak.html)s are locked.
+    pub fn is_locked(&self) -> bool {
+        self.parent.is_locked()
+    }
+
+    /// Unlocks all [`RwLockWeak`](struct.RwLockWeak.html) of this instance.
+    pub fn unlock(&self) {
+        self.parent.unlock()
+    }
+
+    /// Downgrade a [`RwParentArc`](struct.RwParentArc.html) into a
+    /// [`RwLockWeak`](struct.RwLockWeak.html)
+    pub fn downgrade(other: &Self) -> RwLockWeak<T> {
+        RwLockWeak {
+            weak: ParentArc::downgrade(&other.parent),
+        }
+    }
+
+    /// Read the data, waiting for a writer to drop if there is one.
+    ///
+    /// The parent can read while its weak references are locked.
+    pub fn read(&self) -> ReadChildArc<T> {
+        let this = self.parent.header();
+
+        loop {
+            match try_read_parent(&self.parent) {
+                Some(reader) => return reader,
+                None => this.until_writer_released(),
+            }
+        }
+    }
+
+    /// Read the data without waiting, fails while a writer holds the data or waits for it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{RwParentArc, RwLockWeak};
+    /// fn main() {
+    ///     let parent = RwParentArc::new(0);
+    ///     let weak: RwLockWeak<_> = RwParentArc::downgrade(&parent);
+    ///
+    ///     let writer = weak.write().unwrap();
+    ///     assert!(parent.try_read().is_none());
+    ///     drop(writer);
+    ///     assert_eq!(*parent.try_read().unwrap(), 0);
+    /// }
+    /// ```
+    pub fn try_read(&self) -> Option<ReadChildArc<T>> {
+        try_read_parent(&self.parent)
+    }
+
+    /// Get exclusive access to the data without waiting, fails while other children are alive.
+    ///
+    /// The parent can write while its weak references are locked.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{RwParentArc, RwLockWeak};
+    /// fn main() {
+    ///     let parent = RwParentArc::new(0);
+    ///     let weak: RwLockWeak<_> = RwParentArc::downgrade(&parent);
+    ///
+    ///     let reader = weak.read().unwrap();
+    ///     assert!(parent.try_write().is_none());
+    ///     drop(reader);
+    ///     *parent.try_write().unwrap() += 1;
+    ///     assert_eq!(*weak.read().unwrap(), 1);
+    /// }
+    /// ```
+    pub fn try_write(&self) -> Option<WriteChildArc<T>> {
+        let this = self.parent.header();
+
+        if !this.acquire_write() {
+            return None;
+        }
+        if this.strong.load(Ordering::SeqCst) != WRITER {
+            // Let the readers that backed off in the meantime through
+            this.release_write();
+            return None;
+        }
+        this.retain();
+        Some(WriteChildArc {
+            header: self.parent.ptr.cast(),
+            data: Womb::data(self.parent.ptr),
+        })
+    }
+
+    /// Blocks the thread until all children of this instance have dropped, returning the
+    /// underlying data. See [`ParentArc::block_into_inner`](struct.ParentArc.html#method.block_into_inner).
+    pub fn block_into_inner(self) -> T {
+        self.parent.block_into_inner().into_inner()
+    }
+
+    /// Asynchronous version of [`block_into_inner`](#method.block_into_inner), see
+    /// [`ParentArc::into_inner_async`](struct.ParentArc.html#method.into_inner_async).
+    ///
+    /// It does not require `T: Send`, readers and writers only leave the thread of the parent when
+    /// `T` is `Send` and `Sync`, so the last one drops the data of a dropped future on that thread.
+    pub fn into_inner_async(self) -> RwIntoInner<T> {
+        RwIntoInner {
+            inner: self.parent.lock_into_inner(),
+        }
+    }
+
+    /// Check wether two parents own the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ParentArc::ptr_eq(&this.parent, &other.parent)
+    }
+}
+
+fn try_read_parent<T>(parent: &ParentArc<RwData<T>>) -> Option<ReadChildArc<T>> {
+    if !parent.header().acquire_read() {
+        return None;
+    }
+    Some(ReadChildArc {
+        child: ChildArc::from(parent.ptr.cast(), Womb::data(parent.ptr)),
+    })
+}
+
+/// Future returned by [`into_inner_async`](struct.RwParentArc.html#method.into_inner_async).
+#[must_use = "futures do nothing unless polled or awaited"]
+pub struct RwIntoInner<T> {
+    inner: IntoInner<RwData<T>>,
+}
+
+impl<T> RwIntoInner<T> {
+    /// Read the underlying data without waiting, fails while a writer holds the data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future has already completed.
+    pub fn try_read(&self) -> Option<ReadChildArc<T>> {
+        try_read_parent(self.parent())
+    }
+
+    fn parent(&self) -> &ParentArc<RwData<T>> {
+        self.inner
+            .parent
+            .as_ref()
+            .expect("IntoInner polled after completion")
+    }
+}
+
+impl<T> Future for RwIntoInner<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner).poll(cx).map(RwData::into_inner)
+    }
+}
+
+/// Weak reference to a [`RwParentArc`](struct.RwParentArc.html).
+///
+/// It upgrades into a reader or a writer as long as the parent has not locked it.
+pub struct RwLockWeak<T> {
+    weak: LockWeak<RwData<T>>,
+}
+
+impl<T> RwLockWeak<T> {
+    /// Upgrades this Weak reference into a shared [`ReadChildArc`](struct.ReadChildArc.html),
+    /// waiting for a writer to drop if there is one. Fails like
+    /// [`LockWeak::upgrade`](struct.LockWeak.html#method.upgrade) once the parent is locked.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{RwParentArc, RwLockWeak};
+    /// fn main() {
+    ///     let parent = RwParentArc::new(true);
+    ///     let weak: RwLockWeak<_> = RwParentArc::downgrade(&parent);
+    ///
+    ///     let (a, b) = (weak.read().unwrap(), weak.read().unwrap());
+    ///     assert!(*a && *b);
+    /// }
+    /// ```
+    pub fn read(&self) -> Result<ReadChildArc<T>, UpgradeError> {
+        let this = self.weak.header();
+
+        loop {
+            // Same as LockWeak::upgrade, the reader is counted before checking the lock
+            let free = this.acquire_read();
+            atomic::fence(Ordering::SeqCst);
+            if this.lock.load(Ordering::SeqCst) {
+                if free {
+                    this.release_strong();
+                }
+                return Err(this.upgrade_error());
+            }
+            if free {
+                return Ok(ReadChildArc {
+                    child: ChildArc::from(self.weak.header, self.weak.data),
+                });
+            }
+            this.until_writer_released();
+        }
+    }
+
+    /// Upgrades this Weak reference into an exclusive [`WriteChildArc`](struct.WriteChildArc.html),
+    /// waiting for the other children to drop. Fails like
+    /// [`LockWeak::upgrade`](struct.LockWeak.html#method.upgrade) once the parent is locked.
+    ///
+    /// New readers wait for a writer as soon as it starts waiting so they cannot starve it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use parc::{RwParentArc, RwLockWeak};
+    /// fn main() {
+    ///     let parent = RwParentArc::new(0);
+    ///     let weak: RwLockWeak<_> = RwParentArc::downgrade(&parent);
+    ///
+    ///     *weak.write().unwrap() += 1;
+    ///     assert_eq!(*weak.read().unwrap(), 1);
+    /// }
+    /// ```
+    pub fn write(&self) -> Result<WriteChildArc<T>, UpgradeError> {
+        let this = self.weak.header();
+
+        loop {
+            let free = this.acquire_write();
+            atomic::fence(Ordering::SeqCst);
+            if this.lock.load(Ordering::SeqCst) {
+                if free {
+                    this.release_write();
+                }
+                return Err(this.upgrade_error());
+            }
+            if free {
+                break;
+            }
+            this.until_writer_released();
+        }
+
+        this.until_readers_released();
+        this.retain();
+        Ok(WriteChildArc {
+            header: self.weak.header,
+            data: self.weak.data,
+        })
+    }
+
+    /// Check wether two weak references point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        LockWeak::ptr_eq(&this.weak, &other.weak)
+    }
+}
+
+impl<T> Clone for RwLockWeak<T> {
+    fn clone(&self) -> Self {
+        Self {
+            weak: self.weak.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for RwLockWeak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(RwLockWeak)")
+    }
+}
+
+/// Weak references are equal if they point to the same allocation.
+impl<T> PartialEq for RwLockWeak<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.weak == other.weak
+    }
+}
+
+impl<T> Eq for RwLockWeak<T> {}
+
+impl<T> Hash for RwLockWeak<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.weak.hash(state)
+    }
+}
+
+/// Shared reader of a [`RwParentArc`](struct.RwParentArc.html), it can be dereferenced into the
+/// underlying data.
+pub struct ReadChildArc<T> {
+    child: ChildArc<RwData<T>>,
+}
+
+impl<T> ops::Deref for ReadChildArc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // safe because writers wait for the readers counted in strong
+        unsafe { &*self.child.0.get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ReadChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for ReadChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Exclusive writer of a [`RwParentArc`](struct.RwParentArc.html), it can be mutably dereferenced
+/// into the underlying data.
+pub struct WriteChildArc<T> {
+    header: NonNull<Header>,
+    data: NonNull<RwData<T>>,
+}
+
+impl<T> WriteChildArc<T> {
+    fn header(&self) -> &Header {
+        // safe because the writer bit is set
+        unsafe { self.header.as_ref() }
+    }
+}
+
+impl<T> ops::Deref for WriteChildArc<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data.as_ref().0.get() }
+    }
+}
+
+impl<T> ops::DerefMut for WriteChildArc<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // safe because there are no other children while the writer bit is held
+        unsafe { &mut *self.data.as_ref().0.get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for WriteChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for WriteChildArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Send + Sync> Send for WriteChildArc<T> {}
+unsafe impl<T: Send + Sync> Sync for WriteChildArc<T> {}
+
+impl<T> Drop for WriteChildArc<T> {
+    fn drop(&mut self) {
+        self.header().release_write();
+        unsafe { Header::release(self.header) }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn many_writers() {
+        let m = RwParentArc::new(0usize);
+
+        let vh: Vec<_> = (0..8)
+            .map(|_| {
+                let weak = RwParentArc::downgrade(&m);
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        *weak.write().unwrap() += 1;
+                    }
+                })
+            })
+            .collect();
+        for h in vh {
+            h.join().unwrap();
+        }
+
+        assert_eq!(m.block_into_inner(), 800);
+    }
+
+    #[test]
+    fn writer_waits_for_readers() {
+        let m = RwParentArc::new(0usize);
+        let reader = m.read();
+
+        let writer = thread::spawn({
+            let weak = RwParentArc::downgrade(&m);
+            move || *weak.write().unwrap() += 1
+        });
+        // Readers are refused once the writer holds its bit and waits
+        while m.try_read().is_some() {
+            thread::yield_now();
+        }
+        assert_eq!(*reader, 0);
+
+        // The parked writer is woken by the last reader
+        drop(reader);
+        writer.join().unwrap();
+        assert_eq!(*m.read(), 1);
+        assert_eq!(m.block_into_inner(), 1);
+    }
+
+    #[test]
+    fn readers_see_whole_writes() {
+        let m = RwParentArc::new((0usize, 0usize));
+
+        let writer = thread::spawn({
+            let weak = RwParentArc::downgrade(&m);
+            move || {
+                while let Ok(mut pair) = weak.write() {
+                    pair.0 += 1;
+                    pair.1 += 1;
+                }
+            }
+        });
+        let vh: Vec<_> = (0..4)
+            .map(|_| {
+                let weak = RwParentArc::downgrade(&m);
+                thread::spawn(move || {
+                    while let Ok(pair) = weak.read() {
+                        assert_eq!(pair.0, pair.1);
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..100 {
+            let pair = m.read();
+            assert_eq!(pair.0, pair.1);
+        }
+        let (a, b) = m.block_into_inner();
+        assert_eq!(a, b);
+        writer.join().unwrap();
+        for h in vh {
+            h.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn locked_upgrades() {
+        let m = RwParentArc::new(0);
+        let weak = RwParentArc::downgrade(&m);
+
+        m.lock();
+        assert_eq!(weak.read().err(), Some(UpgradeError::Locked));
+        assert_eq!(weak.write().err(), Some(UpgradeError::Locked));
+        assert_eq!(*m.read(), 0); // The parent can still read
+
+        m.unlock();
+        *weak.write().unwrap() = 2;
+        assert_eq!(futures::executor::block_on(m.into_inner_async()), 2);
+        assert_eq!(weak.read().err(), Some(UpgradeError::Dead));
+    }
+
+    #[test]
+    fn locked_parent_writes() {
+        let m = RwParentArc::new(0);
+        let weak = RwParentArc::downgrade(&m);
+
+        m.lock();
+        *m.try_write().unwrap() += 1; // The parent can still write
+        assert_eq!(weak.write().err(), Some(UpgradeError::Locked));
+        assert_eq!(*m.read(), 1);
+
+        m.unlock();
+        *weak.write().unwrap() += 1;
+        assert_eq!(m.block_into_inner(), 2);
+    }
+
+    #[test]
+    fn drop_pending_into_inner() {
+        let m = RwParentArc::new(std::vec![1]);
+        let weak = RwParentArc::downgrade(&m);
+        let reader = weak.read().unwrap();
+
+        let fut = m.into_inner_async();
+        assert_eq!(*fut.try_read().unwrap(), [1]);
+        drop(fut);
+
+        // The reader keeps the data alive, the writer bit is never taken again
+        assert_eq!(weak.write().err(), Some(UpgradeError::Dead));
+        assert_eq!(*reader, [1]);
+    }
+
+    #[test]
+    fn drop_pending_into_inner_local() {
+        // Data that is not Send is dropped by the last reader, on the thread of the parent
+        let data = std::rc::Rc::new(1);
+        let m = RwParentArc::new(data.clone());
+        let reader = m.read();
+
+        drop(m.into_inner_async());
+        assert_eq!(std::rc::Rc::strong_count(&data), 2);
+        drop(reader);
+        assert_eq!(std::rc::Rc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn try_read_with_writer() {
+        let m = RwParentArc::new(0);
+        let weak = RwParentArc::downgrade(&m);
+
+        let writer = weak.write().unwrap();
+        assert!(m.try_read().is_none());
+        drop(writer);
+        assert_eq!(*m.try_read().unwrap(), 0);
+
+        // Writers created before the lock still hold off the readers of the future
+        let writer = weak.write().unwrap();
+        let fut = m.into_inner_async();
+        assert!(fut.try_read().is_none());
+        drop(writer);
+        assert_eq!(*fut.try_read().unwrap(), 0);
+        assert_eq!(futures::executor::block_on(fut), 0);
+    }
+
+    #[test]
+    fn try_write_with_children() {
+        let m = RwParentArc::new(0);
+        let weak = RwParentArc::downgrade(&m);
+
+        let reader = weak.read().unwrap();
+        assert!(m.try_write().is_none());
+        assert_eq!(*weak.read().unwrap(), 0); // The failed write does not hold off the readers
+        drop(reader);
+
+        let writer = weak.write().unwrap();
+        assert!(m.try_write().is_none());
+        drop(writer);
+
+        *m.try_write().unwrap() += 1;
+        assert_eq!(m.block_into_inner(), 1);
+    }
+}
@@ -0,0 +1,321 @@
+//! Model checked races between the parent and its children, run with
+//! `RUSTFLAGS="--cfg loom" cargo test -p parc --test loom --release`.
+
+#![cfg(loom)]
+
+use loom::cell::UnsafeCell;
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+
+use parc::{ParentArc, RwParentArc, TryUnwrapError, UpgradeError};
+
+// Data written by the children and read back by the parent, loom reports any unsynchronized access
+struct Cell(UnsafeCell<usize>);
+
+unsafe impl Sync for Cell {}
+
+impl Cell {
+    fn new() -> Self {
+        Self(UnsafeCell::new(0))
+    }
+
+    fn read(&self) -> usize {
+        self.0.with(|p| unsafe { *p })
+    }
+
+    fn write(&mut self, value: usize) {
+        self.0.with_mut(|p| unsafe { *p = value })
+    }
+}
+
+#[test]
+fn upgrade_block_into_inner() {
+    loom::model(|| {
+        let parent = ParentArc::new(Cell::new());
+        let weak = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || weak.upgrade().ok().map(|child| child.read()));
+
+        // A child that upgraded must have dropped before the data is handed out
+        let mut cell = parent.block_into_inner();
+        cell.write(1);
+
+        assert!(matches!(h.join().unwrap(), None | Some(0)));
+    });
+}
+
+#[test]
+fn upgrade_try_unwrap() {
+    loom::model(|| {
+        let parent = ParentArc::new(Cell::new());
+        let weak = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || weak.upgrade().ok().map(|child| child.read()));
+
+        match ParentArc::try_unwrap(parent) {
+            Ok(mut cell) => cell.write(1),
+            Err(TryUnwrapError::WouldLock(parent)) | Err(TryUnwrapError::WouldBlock(parent)) => {
+                assert!(!parent.is_locked());
+                let mut cell = parent.block_into_inner();
+                cell.write(1);
+            }
+        }
+
+        assert!(matches!(h.join().unwrap(), None | Some(0)));
+    });
+}
+
+#[test]
+fn upgrade_lock_unlock() {
+    loom::model(|| {
+        let parent = ParentArc::new(AtomicUsize::new(0));
+        let weak = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || {
+            if let Ok(child) = weak.upgrade() {
+                child.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        parent.lock();
+        parent.unlock();
+        h.join().unwrap();
+
+        assert!(parent.block_into_inner().load(Ordering::SeqCst) <= 1);
+    });
+}
+
+// Counts the drops of the data, loom reports the leaked Arc if it is never dropped
+struct Tracked(Arc<AtomicUsize>);
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn drop_parent_with_children() {
+    loom::model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let parent = ParentArc::new(Tracked(Arc::clone(&drops)));
+        let weak = ParentArc::downgrade(&parent);
+        let kept = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || {
+            if let Ok(child) = weak.upgrade() {
+                assert_eq!(child.0.load(Ordering::SeqCst), 0);
+            }
+        });
+
+        drop(parent);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+        // The weak reference outlives the parent and the allocation is freed by the last one
+        assert_eq!(kept.upgrade().err(), Some(UpgradeError::Dead));
+        h.join().unwrap();
+    });
+}
+
+#[test]
+fn drop_pending_into_inner() {
+    loom::model(|| {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let parent = ParentArc::new(Tracked(Arc::clone(&drops)));
+        let weak = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || {
+            if let Ok(child) = weak.upgrade() {
+                assert_eq!(child.0.load(Ordering::SeqCst), 0);
+            }
+        });
+
+        // The parent or the child drops the data, never both
+        drop(parent.into_inner_async());
+        h.join().unwrap();
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn async_into_inner() {
+    loom::model(|| {
+        let parent = ParentArc::new(Cell::new());
+        let weak = ParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || weak.upgrade().ok().map(|child| child.read()));
+
+        let mut cell = loom::future::block_on(parent.into_inner_async());
+        cell.write(1);
+
+        assert!(matches!(h.join().unwrap(), None | Some(0)));
+    });
+}
+
+#[test]
+fn upgrade_async_unlock() {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(2);
+    builder.check(|| {
+        let parent = ParentArc::new(Cell::new());
+        let weak = ParentArc::downgrade(&parent);
+        parent.lock();
+
+        let h = thread::spawn(move || {
+            loom::future::block_on(weak.upgrade_async()).map(|child| child.read())
+        });
+
+        // The waiting child is woken by the unlock, or by the parent dying if it missed it
+        parent.unlock();
+        let mut cell = parent.block_into_inner();
+        cell.write(1);
+
+        assert!(matches!(h.join().unwrap(), Ok(0) | Err(UpgradeError::Dead)));
+    });
+}
+
+#[test]
+fn two_upgrade_async_unlock() {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(2);
+    builder.check(|| {
+        let parent = ParentArc::new(Cell::new());
+        parent.lock();
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let weak = ParentArc::downgrade(&parent);
+                thread::spawn(move || {
+                    loom::future::block_on(weak.upgrade_async()).map(|child| child.read())
+                })
+            })
+            .collect();
+
+        // A registration taking the stack out while the unlock drains it wakes the waiters it
+        // puts back, the parent stays alive so only the unlock can wake them
+        parent.unlock();
+        for h in handles {
+            assert!(matches!(h.join().unwrap(), Ok(0)));
+        }
+        let _ = parent.block_into_inner();
+    });
+}
+
+#[test]
+fn two_children_block_into_inner() {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(2);
+    builder.check(|| {
+        let parent = ParentArc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let weak = ParentArc::downgrade(&parent);
+                thread::spawn(move || {
+                    if let Ok(child) = weak.upgrade() {
+                        child.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+
+        // Increments of the children that upgraded are visible once the data is handed out
+        let count = parent.block_into_inner();
+        let seen = count.load(Ordering::Relaxed);
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(seen <= 2);
+    });
+}
+
+#[test]
+fn rw_write_block_into_inner() {
+    loom::model(|| {
+        let parent = RwParentArc::new(Cell::new());
+        let weak = RwParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || weak.write().map(|mut cell| cell.write(1)).is_ok());
+
+        // The parent reads and takes the data only while the writer does not hold it
+        let read = parent.read().read();
+        let mut cell = parent.block_into_inner();
+        let written = h.join().unwrap();
+        assert!(read <= written as usize && cell.read() == written as usize);
+        cell.write(2);
+    });
+}
+
+#[test]
+fn rw_write_read() {
+    loom::model(|| {
+        let parent = RwParentArc::new(Cell::new());
+        let weak = RwParentArc::downgrade(&parent);
+
+        let h = thread::spawn({
+            let weak = weak.clone();
+            move || weak.read().map(|cell| cell.read())
+        });
+        weak.write().unwrap().write(1);
+
+        assert!(matches!(h.join().unwrap(), Ok(0) | Ok(1)));
+        assert_eq!(parent.block_into_inner().read(), 1);
+    });
+}
+
+#[test]
+fn rw_try_write_read() {
+    loom::model(|| {
+        let parent = RwParentArc::new(Cell::new());
+        let weak = RwParentArc::downgrade(&parent);
+
+        let h = thread::spawn(move || weak.read().map(|cell| cell.read()));
+
+        // The parent only writes while the reader does not hold the data
+        let written = parent.try_write().map(|mut cell| cell.write(1)).is_some();
+        let read = h.join().unwrap().unwrap();
+        assert!(read <= written as usize);
+        assert_eq!(parent.block_into_inner().read(), written as usize);
+    });
+}
+
+#[test]
+fn mapped_upgrade_block_into_inner() {
+    loom::model(|| {
+        let parent = ParentArc::new((Cell::new(), Cell::new()));
+        let weak = ParentArc::downgrade(&parent).map(|t| &t.1).unwrap();
+
+        let h = thread::spawn(move || weak.upgrade().ok().map(|child| child.read()));
+
+        // The projected child holds the whole allocation until it drops
+        let (_, mut cell) = parent.block_into_inner();
+        cell.write(1);
+
+        assert!(matches!(h.join().unwrap(), None | Some(0)));
+    });
+}
+
+#[test]
+fn split_block_into_inner() {
+    loom::model(|| {
+        let parent = ParentArc::new(Cell::new());
+        let weak = ParentArc::downgrade(&parent);
+        let mut co_parents = parent.split(2);
+        let other = co_parents.pop().unwrap();
+
+        let h = thread::spawn(move || {
+            let read = weak.upgrade().ok().map(|child| child.read());
+            (read, other.block_into_inner())
+        });
+
+        // Whichever co-parent agrees last waits for the child and gets the data
+        let mine = co_parents.pop().unwrap().block_into_inner();
+        let (read, theirs) = h.join().unwrap();
+        let mut cell = match (mine, theirs) {
+            (Some(cell), None) | (None, Some(cell)) => cell,
+            _ => unreachable!(),
+        };
+        cell.write(1);
+
+        assert!(matches!(read, None | Some(0)));
+    });
+}
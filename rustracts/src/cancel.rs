@@ -0,0 +1,124 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::task::{AtomicWaker, Context, Poll};
+
+// Flags of the state, a contract is cancelled only if it had not finished before
+const CANCELLED: usize = 1;
+const FINISHED: usize = 2;
+
+struct CancelState {
+    flags: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+impl CancelState {
+    fn is(&self, flag: usize) -> bool {
+        self.flags.load(Ordering::SeqCst) & flag != 0
+    }
+}
+
+/// Thread-safe handle to cancel a running contract.
+///
+/// Cancelling wakes the contract right away, it terminates with
+/// [`Status::Cancelled`](enum.Status.html#variant.Cancelled) and hands back its context once the
+/// readers of the context have dropped.
+#[derive(Clone)]
+pub struct ContractHandle {
+    state: Arc<CancelState>,
+}
+
+impl ContractHandle {
+    /// Cancel the contract, this does nothing if it has already terminated.
+    pub fn cancel(&self) {
+        let cancelled =
+            self.state
+                .flags
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |flags| {
+                    (flags == 0).then_some(CANCELLED)
+                });
+        if cancelled.is_ok() {
+            self.state.waker.wake();
+        }
+    }
+
+    /// Check wether the contract has been cancelled through one of its handles before it
+    /// terminated.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.is(CANCELLED)
+    }
+
+    /// Check wether the contract has terminated or has been dropped.
+    pub fn is_finished(&self) -> bool {
+        self.state.is(FINISHED)
+    }
+}
+
+impl fmt::Debug for ContractHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ContractHandle")
+            .field("cancelled", &self.is_cancelled())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Handles are equal if they cancel the same contract.
+impl PartialEq for ContractHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for ContractHandle {}
+
+impl Hash for ContractHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.state).hash(state)
+    }
+}
+
+/// Cancellation state owned by a contract, it is finished once the contract terminates or drops.
+pub(crate) struct Cancel {
+    state: Arc<CancelState>,
+}
+
+impl Cancel {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(CancelState {
+                flags: AtomicUsize::new(0),
+                waker: AtomicWaker::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> ContractHandle {
+        ContractHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Check wether the contract has been cancelled, the task will be woken when a handle cancels
+    /// it.
+    pub(crate) fn poll_cancelled(&self, cx: &mut Context) -> bool {
+        self.state.waker.register(cx.waker());
+        self.state.is(CANCELLED)
+    }
+
+    /// Mark the contract finished once it produced its status.
+    pub(crate) fn finish<T>(&self, poll: Poll<T>) -> Poll<T> {
+        if poll.is_ready() {
+            self.state.flags.fetch_or(FINISHED, Ordering::SeqCst);
+        }
+        poll
+    }
+}
+
+impl Drop for Cancel {
+    fn drop(&mut self) {
+        self.state.flags.fetch_or(FINISHED, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,924 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextErrorKind};
+use crate::{Contract, ContractExt, ContractHandle, Status};
+
+use futures::{
+    future::{Either, FusedFuture, Future},
+    ready,
+    task::{Context, Poll},
+};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Contract for the [`map`](../trait.ContractExt.html#method.map) method.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct Map<A, F> {
+        #[pin]
+        contract: A,
+        f: Option<F>,
+    }
+}
+
+impl<A, F> Map<A, F> {
+    pub(crate) fn new(contract: A, f: F) -> Self {
+        Self {
+            contract,
+            f: Some(f),
+        }
+    }
+}
+
+impl<A, F, R, C, U> Map<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(R) -> U,
+{
+    fn map(self: Pin<&mut Self>, status: Status<R, C>) -> Status<U, C> {
+        let f = self
+            .project()
+            .f
+            .take()
+            .expect("Cannot poll after completion");
+        status.map(f)
+    }
+}
+
+impl<A, F, R, C, U> Contract for Map<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(R) -> U,
+{
+    fn poll_valid(&self) -> bool {
+        self.contract.poll_valid()
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.contract.remaining()
+    }
+
+    fn poll_execute(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_execute(cx));
+        Poll::Ready(self.map(status))
+    }
+
+    fn poll_void(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_void(cx));
+        Poll::Ready(self.map(status))
+    }
+
+    fn poll_cancel(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_cancel(cx));
+        Poll::Ready(self.map(status))
+    }
+}
+
+impl<A, F, R, C, U> ContractExt for Map<A, F>
+where
+    A: ContractExt<Output = Status<R, C>>,
+    F: FnOnce(R) -> U,
+{
+    type Context = A::Context;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.contract.get_context()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.contract.get_handle()
+    }
+}
+
+impl<A, F, R, C, U> Future for Map<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(R) -> U,
+{
+    type Output = Status<U, C>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll(cx));
+        Poll::Ready(self.map(status))
+    }
+}
+
+impl<A, F, R, C, U> FusedFuture for Map<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(R) -> U,
+{
+    fn is_terminated(&self) -> bool {
+        self.f.is_none()
+    }
+}
+
+pin_project! {
+    /// Contract for the [`map_context`](../trait.ContractExt.html#method.map_context) method.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct MapContext<A, F> {
+        #[pin]
+        contract: A,
+        f: Option<F>,
+    }
+}
+
+impl<A, F> MapContext<A, F> {
+    pub(crate) fn new(contract: A, f: F) -> Self {
+        Self {
+            contract,
+            f: Some(f),
+        }
+    }
+}
+
+impl<A, F, R, C, D> MapContext<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(C) -> D,
+{
+    fn map(self: Pin<&mut Self>, status: Status<R, C>) -> Status<R, D> {
+        let f = self
+            .project()
+            .f
+            .take()
+            .expect("Cannot poll after completion");
+        status.map_context(f)
+    }
+}
+
+impl<A, F, R, C, D> Contract for MapContext<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(C) -> D,
+{
+    fn poll_valid(&self) -> bool {
+        self.contract.poll_valid()
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.contract.remaining()
+    }
+
+    fn poll_execute(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_execute(cx));
+        Poll::Ready(self.map(status))
+    }
+
+    fn poll_void(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_void(cx));
+        Poll::Ready(self.map(status))
+    }
+
+    fn poll_cancel(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll_cancel(cx));
+        Poll::Ready(self.map(status))
+    }
+}
+
+impl<A, F, R, C, D> ContractExt for MapContext<A, F>
+where
+    A: ContractExt<Output = Status<R, C>>,
+    F: FnOnce(C) -> D,
+{
+    type Context = A::Context;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.contract.get_context()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.contract.get_handle()
+    }
+}
+
+impl<A, F, R, C, D> Future for MapContext<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(C) -> D,
+{
+    type Output = Status<R, D>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let status = ready!(self.as_mut().project().contract.poll(cx));
+        Poll::Ready(self.map(status))
+    }
+}
+
+impl<A, F, R, C, D> FusedFuture for MapContext<A, F>
+where
+    A: Contract<Output = Status<R, C>>,
+    F: FnOnce(C) -> D,
+{
+    fn is_terminated(&self) -> bool {
+        self.f.is_none()
+    }
+}
+
+pin_project! {
+    /// Contract for the [`map_handle`](../trait.ContractExt.html#method.map_handle) method.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct MapHandle<A, F> {
+        #[pin]
+        contract: A,
+        f: F,
+    }
+}
+
+impl<A, F> MapHandle<A, F> {
+    pub(crate) fn new(contract: A, f: F) -> Self {
+        Self { contract, f }
+    }
+}
+
+impl<A, F> Contract for MapHandle<A, F>
+where
+    A: Contract,
+{
+    fn poll_valid(&self) -> bool {
+        self.contract.poll_valid()
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.contract.remaining()
+    }
+
+    fn poll_execute(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.project().contract.poll_execute(cx)
+    }
+
+    fn poll_void(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.project().contract.poll_void(cx)
+    }
+
+    fn poll_cancel(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.project().contract.poll_cancel(cx)
+    }
+}
+
+impl<A, F, H> ContractExt for MapHandle<A, F>
+where
+    A: ContractExt,
+    F: Fn(A::Context) -> H,
+{
+    type Context = H;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.contract.get_context().map(&self.f)
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.contract.get_handle()
+    }
+}
+
+impl<A, F> Future for MapHandle<A, F>
+where
+    A: Contract,
+{
+    type Output = A::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.project().contract.poll(cx)
+    }
+}
+
+impl<A, F> FusedFuture for MapHandle<A, F>
+where
+    A: Contract + FusedFuture,
+{
+    fn is_terminated(&self) -> bool {
+        self.contract.is_terminated()
+    }
+}
+
+// What is being asked of the underlying contracts.
+#[derive(Copy, Clone)]
+enum Action {
+    Poll,
+    Execute,
+    Void,
+    Cancel,
+}
+
+impl Action {
+    fn drive<C>(self, contract: Pin<&mut C>, cx: &mut Context) -> Poll<C::Output>
+    where
+        C: Contract,
+    {
+        match self {
+            Action::Poll => contract.poll(cx),
+            Action::Execute => contract.poll_execute(cx),
+            Action::Void => contract.poll_void(cx),
+            Action::Cancel => contract.poll_cancel(cx),
+        }
+    }
+
+    // The contract built once the first one terminated runs to its own term, only a cancel is
+    // forwarded to it.
+    fn next(self) -> Self {
+        match self {
+            Action::Cancel => Action::Cancel,
+            Action::Poll | Action::Execute | Action::Void => Action::Poll,
+        }
+    }
+}
+
+pin_project! {
+    #[project = ChainProj]
+    enum Chain<A, G, B> {
+        First {
+            #[pin]
+            contract: A,
+            next: Option<G>,
+        },
+        Second {
+            #[pin]
+            contract: B,
+        },
+        Done,
+    }
+}
+
+impl<A, G, B> Chain<A, G, B>
+where
+    A: Contract,
+    B: Contract,
+{
+    fn poll_valid(&self) -> bool {
+        match self {
+            Chain::First { contract, .. } => contract.poll_valid(),
+            Chain::Second { contract } => contract.poll_valid(),
+            Chain::Done => false,
+        }
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        match self {
+            Chain::First { contract, .. } => contract.remaining(),
+            Chain::Second { contract } => contract.remaining(),
+            Chain::Done => None,
+        }
+    }
+
+    fn get_context(&self) -> Result<A::Context, ContextError>
+    where
+        A: ContractExt,
+    {
+        match self {
+            Chain::First { contract, .. } => contract.get_context(),
+            _ => Err(ContextError::from(ContextErrorKind::ExpiredContext)),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self, Chain::Done)
+    }
+
+    // Run `action` on the first contract, `step` either builds the second contract from the
+    // output of the first one or ends the chain, `finish` converts the output of the second one.
+    fn drive<O>(
+        mut self: Pin<&mut Self>,
+        action: Action,
+        cx: &mut Context,
+        step: fn(G, A::Output) -> Result<B, O>,
+        finish: fn(B::Output) -> O,
+    ) -> Poll<O> {
+        loop {
+            match self.as_mut().project() {
+                ChainProj::First { contract, next } => {
+                    let output = ready!(action.drive(contract, cx));
+                    let next = next.take().expect("Cannot poll after completion");
+                    match step(next, output) {
+                        Ok(contract) => self.set(Chain::Second { contract }),
+                        Err(output) => {
+                            self.set(Chain::Done);
+                            return Poll::Ready(output);
+                        }
+                    }
+                }
+                ChainProj::Second { contract } => {
+                    let output = ready!(action.next().drive(contract, cx));
+                    self.set(Chain::Done);
+                    return Poll::Ready(finish(output));
+                }
+                ChainProj::Done => panic!("Cannot poll after completion"),
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Contract for the [`and_then`](../trait.ContractExt.html#method.and_then) method.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct AndThen<A, G, B> {
+        #[pin]
+        chain: Chain<A, G, B>,
+        cancel: Cancel,
+    }
+}
+
+impl<A, G, B> AndThen<A, G, B> {
+    pub(crate) fn new(contract: A, g: G) -> Self {
+        Self {
+            chain: Chain::First {
+                contract,
+                next: Some(g),
+            },
+            cancel: Cancel::new(),
+        }
+    }
+}
+
+impl<A, G, B, R, C, U, D> AndThen<A, G, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<U, D>>,
+    G: FnOnce(R) -> B,
+{
+    fn step(g: G, status: Status<R, C>) -> Result<B, Status<U, Either<C, D>>> {
+        match status {
+            Status::Completed(value) => Ok(g(value)),
+            Status::Voided(context) => Err(Status::Voided(Either::Left(context))),
+            Status::Expired(context) => Err(Status::Expired(Either::Left(context))),
+            Status::NotRealised(context) => Err(Status::NotRealised(Either::Left(context))),
+            Status::Cancelled(context) => Err(Status::Cancelled(Either::Left(context))),
+        }
+    }
+
+    fn finish(status: Status<U, D>) -> Status<U, Either<C, D>> {
+        status.map_context(Either::Right)
+    }
+}
+
+impl<A, G, B, R, C, U, D> Contract for AndThen<A, G, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<U, D>>,
+    G: FnOnce(R) -> B,
+{
+    fn poll_valid(&self) -> bool {
+        self.chain.poll_valid()
+    }
+
+    /// Time left before the running contract expires.
+    fn remaining(&self) -> Option<Duration> {
+        self.chain.remaining()
+    }
+
+    fn poll_execute(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel.finish(
+            this.chain
+                .drive(Action::Execute, cx, Self::step, Self::finish),
+        )
+    }
+
+    fn poll_void(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel
+            .finish(this.chain.drive(Action::Void, cx, Self::step, Self::finish))
+    }
+
+    fn poll_cancel(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel.finish(
+            this.chain
+                .drive(Action::Cancel, cx, Self::step, Self::finish),
+        )
+    }
+}
+
+impl<A, G, B, R, C, U, D> ContractExt for AndThen<A, G, B>
+where
+    A: ContractExt<Output = Status<R, C>>,
+    B: Contract<Output = Status<U, D>>,
+    G: FnOnce(R) -> B,
+{
+    type Context = A::Context;
+
+    /// The context of the first contract, it expires once the second contract has started.
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.chain.get_context()
+    }
+
+    /// Cancels the contract that is running, the first one or the one built from its output.
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
+    }
+}
+
+impl<A, G, B, R, C, U, D> Future for AndThen<A, G, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<U, D>>,
+    G: FnOnce(R) -> B,
+{
+    type Output = Status<U, Either<C, D>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let action = if this.cancel.poll_cancelled(cx) {
+            Action::Cancel
+        } else {
+            Action::Poll
+        };
+        this.cancel
+            .finish(this.chain.drive(action, cx, Self::step, Self::finish))
+    }
+}
+
+impl<A, G, B, R, C, U, D> FusedFuture for AndThen<A, G, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<U, D>>,
+    G: FnOnce(R) -> B,
+{
+    fn is_terminated(&self) -> bool {
+        self.chain.is_done()
+    }
+}
+
+pin_project! {
+    /// Contract for the [`or_else`](../trait.ContractExt.html#method.or_else) method.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct OrElse<A, H, B> {
+        #[pin]
+        chain: Chain<A, H, B>,
+        cancel: Cancel,
+    }
+}
+
+impl<A, H, B> OrElse<A, H, B> {
+    pub(crate) fn new(contract: A, h: H) -> Self {
+        Self {
+            chain: Chain::First {
+                contract,
+                next: Some(h),
+            },
+            cancel: Cancel::new(),
+        }
+    }
+}
+
+impl<A, H, B, R, C, D> OrElse<A, H, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<R, D>>,
+    H: FnOnce(C) -> B,
+{
+    fn step(h: H, status: Status<R, C>) -> Result<B, Status<R, Either<C, D>>> {
+        match status {
+            Status::Completed(value) => Err(Status::Completed(value)),
+            // A cancel ends the chain, the fallback would only be built to be cancelled
+            Status::Cancelled(context) => Err(Status::Cancelled(Either::Left(context))),
+            Status::Voided(context) | Status::Expired(context) | Status::NotRealised(context) => {
+                Ok(h(context))
+            }
+        }
+    }
+
+    fn finish(status: Status<R, D>) -> Status<R, Either<C, D>> {
+        status.map_context(Either::Right)
+    }
+}
+
+impl<A, H, B, R, C, D> Contract for OrElse<A, H, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<R, D>>,
+    H: FnOnce(C) -> B,
+{
+    fn poll_valid(&self) -> bool {
+        self.chain.poll_valid()
+    }
+
+    /// Time left before the running contract expires.
+    fn remaining(&self) -> Option<Duration> {
+        self.chain.remaining()
+    }
+
+    fn poll_execute(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel.finish(
+            this.chain
+                .drive(Action::Execute, cx, Self::step, Self::finish),
+        )
+    }
+
+    fn poll_void(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel
+            .finish(this.chain.drive(Action::Void, cx, Self::step, Self::finish))
+    }
+
+    fn poll_cancel(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        this.cancel.finish(
+            this.chain
+                .drive(Action::Cancel, cx, Self::step, Self::finish),
+        )
+    }
+}
+
+impl<A, H, B, R, C, D> ContractExt for OrElse<A, H, B>
+where
+    A: ContractExt<Output = Status<R, C>>,
+    B: Contract<Output = Status<R, D>>,
+    H: FnOnce(C) -> B,
+{
+    type Context = A::Context;
+
+    /// The context of the first contract, it expires once the fallback contract has started.
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.chain.get_context()
+    }
+
+    /// Cancels the contract that is running, the first one or the one built from its output.
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
+    }
+}
+
+impl<A, H, B, R, C, D> Future for OrElse<A, H, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<R, D>>,
+    H: FnOnce(C) -> B,
+{
+    type Output = Status<R, Either<C, D>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let action = if this.cancel.poll_cancelled(cx) {
+            Action::Cancel
+        } else {
+            Action::Poll
+        };
+        this.cancel
+            .finish(this.chain.drive(action, cx, Self::step, Self::finish))
+    }
+}
+
+impl<A, H, B, R, C, D> FusedFuture for OrElse<A, H, B>
+where
+    A: Contract<Output = Status<R, C>>,
+    B: Contract<Output = Status<R, D>>,
+    H: FnOnce(C) -> B,
+{
+    fn is_terminated(&self) -> bool {
+        self.chain.is_done()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::cmp::GtContext;
+    use crate::time::{MockClock, Timer};
+    use crate::{Contract, ContractExt, FuturesContract, OnKillContract, Status};
+
+    use futures::future::Either;
+    use futures::task::{Context, Poll};
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn map_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            GtContext(3, 2),
+            |con| -> usize { con.0 + 5 },
+        )
+        .map(|value| value * 2);
+
+        // Context handles of the mapped contract still update it
+        let mcontext = c.get_context().unwrap();
+        if let Some(mut context) = mcontext.write() {
+            context.0 += 2;
+        }
+        clock.advance(Duration::from_secs(1));
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Completed(20)
+        ));
+    }
+
+    #[test]
+    fn and_then_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            3,
+            |con| -> usize { con + 5 },
+        )
+        .and_then({
+            let clock = clock.clone();
+            move |value| {
+                FuturesContract::with_timer(
+                    Timer::with_clock(Duration::from_secs(1), clock),
+                    value,
+                    |con| -> usize { con * 2 },
+                )
+            }
+        });
+
+        let ticker = std::thread::spawn(move || {
+            for _ in 0..20 {
+                std::thread::sleep(Duration::from_millis(1));
+                clock.advance(Duration::from_millis(100));
+            }
+        });
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Completed(16)
+        ));
+        ticker.join().unwrap();
+    }
+
+    #[test]
+    fn and_then_voided_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            GtContext(3, 2),
+            |con| -> usize { con.0 },
+        )
+        .and_then(|value| OnKillContract::new(true, move |_| -> usize { value }));
+
+        let mcontext = c.get_context().unwrap();
+        if let Some(mut context) = mcontext.write() {
+            context.0 = 1;
+        }
+
+        // The second contract is never built, the context of the first one is returned
+        match crate::executor::block_on(c) {
+            Status::Voided(Either::Left(con)) => assert_eq!(con.0, 1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn or_else_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            GtContext(3, 2),
+            |con| -> usize { con.0 + 5 },
+        )
+        .or_else(|con| OnKillContract::new(false, move |_| -> usize { con.0 }));
+
+        // Void the first contract, the fallback is built from its context right away
+        let mcontext = c.get_context().unwrap();
+        if let Some(mut context) = mcontext.write() {
+            context.0 = 1;
+        }
+
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(1)));
+    }
+
+    #[test]
+    fn or_else_completed_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            (),
+            |_| -> usize { 5 },
+        )
+        .or_else(|_| OnKillContract::new(false, |_| -> usize { 1 }));
+        clock.advance(Duration::from_secs(1));
+
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(5)));
+    }
+
+    #[test]
+    fn or_else_cancelled_contract() {
+        let built = Arc::new(AtomicBool::new(false));
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            GtContext(3, 2),
+            |con| -> usize { con.0 + 5 },
+        )
+        .or_else({
+            let built = built.clone();
+            move |con| {
+                built.store(true, Ordering::SeqCst);
+                OnKillContract::new(false, move |_| -> usize { con.0 })
+            }
+        });
+        c.get_handle().cancel();
+
+        // The fallback is not built, the context of the cancelled contract is returned
+        match crate::executor::block_on(c) {
+            Status::Cancelled(Either::Left(con)) => assert_eq!(con.0, 3),
+            _ => unreachable!(),
+        }
+        assert!(!built.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn and_then_cancelled_contract() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            3,
+            |con| -> usize { con + 5 },
+        )
+        .and_then(|value| OnKillContract::new(true, move |_| -> usize { value }));
+        let handle = c.get_handle();
+        clock.advance(Duration::from_secs(1));
+
+        // Cancels the second contract once the first one has completed
+        let canceller = std::thread::spawn({
+            let handle = handle.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                handle.cancel();
+            }
+        });
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Cancelled(Either::Right(true))
+        ));
+        assert!(handle.is_finished());
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn and_then_executed_before_second_term() {
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            3,
+            |con| -> usize { con + 5 },
+        )
+        .and_then({
+            let clock = clock.clone();
+            move |value| {
+                FuturesContract::with_timer(
+                    Timer::with_clock(Duration::from_secs(1), clock),
+                    value,
+                    |con| -> usize { con * 2 },
+                )
+            }
+        });
+        futures::pin_mut!(c);
+        clock.advance(Duration::from_secs(1));
+
+        // Executing the chain executes the first contract, the second one waits for its own term
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(c.as_mut().poll_execute(&mut cx).is_pending());
+        assert_eq!(c.remaining(), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            c.as_mut().poll_execute(&mut cx),
+            Poll::Ready(Status::Completed(16))
+        ));
+    }
+    #[test]
+    fn and_then_cancel_finishes_handles() {
+        let clock = MockClock::new();
+        let first = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            3,
+            |con| -> usize { con + 5 },
+        );
+        let inner = first.get_handle();
+        let c = first.and_then(|value| OnKillContract::new(true, move |_| -> usize { value }));
+        let handle = c.get_handle();
+        futures::pin_mut!(c);
+
+        // Driving the cancel by hand finishes the chain and the contract it cancelled
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(matches!(
+            c.as_mut().poll_cancel(&mut cx),
+            Poll::Ready(Status::Cancelled(Either::Left(3)))
+        ));
+        assert!(handle.is_finished() && inner.is_finished());
+        assert!(!handle.is_cancelled());
+    }
+
+    #[test]
+    fn and_then_system_clock_contract() {
+        // Both contracts are driven by the timers of the enabled runtime feature
+        let c = FuturesContract::new(Duration::from_millis(10), 3, |con| -> usize { con + 5 })
+            .and_then(|value| {
+                FuturesContract::new(Duration::from_millis(10), value, |con| -> usize { con * 2 })
+            });
+
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Completed(16)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,403 @@
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::mem::ManuallyDrop;
+use std::ops;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::{ContextError, ContextErrorKind, ContractContext};
+
+use futures::{
+    ready,
+    task::{AtomicWaker, Context, Poll},
+};
+use parc::{ReadChildArc, RwIntoInner, RwLockWeak, RwParentArc, WriteChildArc};
+
+/// Context owned by a contract along with the state shared with its guards.
+///
+/// Handles read and write it through the reader/writer protocol of its
+/// [`RwParentArc`](../../parc/struct.RwParentArc.html).
+pub(crate) struct ContextCell<C> {
+    context: C,
+    state: Arc<CellState>,
+}
+
+/// Wakeups of the task polling a contract, reachable without reading the context so a handle can
+/// wake the task once it has released the context.
+pub(crate) struct CellState {
+    waker: AtomicWaker,
+    // The task could not read or update the context because of a handle
+    waiting: AtomicBool,
+    // Validity at the last read or update, used while a guard holds the context
+    valid: AtomicBool,
+}
+
+impl<C> ContextCell<C>
+where
+    C: ContractContext,
+{
+    pub(crate) fn new(context: C) -> (RwParentArc<Self>, Arc<CellState>) {
+        let state = Arc::new(CellState {
+            waker: AtomicWaker::new(),
+            waiting: AtomicBool::new(false),
+            valid: AtomicBool::new(context.poll_valid()),
+        });
+        let cell = RwParentArc::new(Self {
+            context,
+            state: state.clone(),
+        });
+        (cell, state)
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        let valid = self.context.poll_valid();
+        self.state.valid.store(valid, Ordering::SeqCst);
+        valid
+    }
+
+    pub(crate) fn into_inner(self) -> C {
+        self.context
+    }
+}
+
+impl CellState {
+    fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::SeqCst)
+    }
+
+    // Called by a handle once it released the context
+    fn released(&self) {
+        if self.waiting.load(Ordering::SeqCst) && self.waiting.swap(false, Ordering::SeqCst) {
+            self.waker.wake();
+        }
+    }
+}
+
+/// Context owned by a contract, it is unwrapped without blocking once the contract terminates.
+pub(crate) enum ContextSlot<C>
+where
+    C: ContractContext,
+{
+    Owned(OwnedCell<C>),
+    Unwrapping(RwIntoInner<ContextCell<C>>, Arc<CellState>),
+    Unwrapped(C),
+    Taken,
+}
+
+/// Cell of a running contract, a dropped contract gives up its context instead of waiting for the
+/// handles reading it as they may be held by the task dropping the contract.
+pub(crate) struct OwnedCell<C>(Option<RwParentArc<ContextCell<C>>>, Arc<CellState>)
+where
+    C: ContractContext;
+
+impl<C> OwnedCell<C>
+where
+    C: ContractContext,
+{
+    /// Check the context validity without blocking, the task will be woken when a handle changes
+    /// it or when the guard it waits for drops.
+    fn poll_valid(&self, cx: &mut Context) -> Poll<bool> {
+        self.poll_access(cx, RwParentArc::try_read)
+            .map(|cell| cell.is_valid())
+    }
+
+    /// Get exclusive access to the context without blocking, the task will be woken when the
+    /// handles it waits for drop.
+    fn poll_write(&self, cx: &mut Context) -> Poll<WriteChildArc<ContextCell<C>>> {
+        self.poll_access(cx, RwParentArc::try_write)
+    }
+
+    fn poll_access<A>(
+        &self,
+        cx: &mut Context,
+        try_access: impl Fn(&RwParentArc<ContextCell<C>>) -> Option<A>,
+    ) -> Poll<A> {
+        let state = &self.1;
+        state.waker.register(cx.waker());
+        if let Some(access) = try_access(self) {
+            return Poll::Ready(access);
+        }
+
+        // A handle dropping in between did not see the flag, try again once it is set
+        state.waiting.store(true, Ordering::SeqCst);
+        match try_access(self) {
+            Some(access) => Poll::Ready(access),
+            None => Poll::Pending,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self.try_read() {
+            Some(cell) => cell.is_valid(),
+            None => self.1.is_valid(),
+        }
+    }
+
+    fn into_inner_async(mut self) -> ContextSlot<C> {
+        let fut = self.0.take().unwrap().into_inner_async();
+        ContextSlot::Unwrapping(fut, self.1.clone())
+    }
+}
+
+impl<C> ops::Deref for OwnedCell<C>
+where
+    C: ContractContext,
+{
+    type Target = RwParentArc<ContextCell<C>>;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl<C> Drop for OwnedCell<C>
+where
+    C: ContractContext,
+{
+    fn drop(&mut self) {
+        if let Some(cell) = self.0.take() {
+            drop(cell.into_inner_async());
+        }
+    }
+}
+
+impl<C> ContextSlot<C>
+where
+    C: ContractContext,
+{
+    pub(crate) fn new(context: C) -> Self {
+        let (cell, state) = ContextCell::new(context);
+        ContextSlot::Owned(OwnedCell(Some(cell), state))
+    }
+
+    /// Check the context validity, the task will be woken when a handle changes it. It is pending
+    /// while a [`ContextGuard`](struct.ContextGuard.html) holds the context.
+    pub(crate) fn poll_valid(&self, cx: &mut Context) -> Poll<bool> {
+        match self {
+            ContextSlot::Owned(cell) => cell.poll_valid(cx),
+            _ => Poll::Ready(self.is_valid()),
+        }
+    }
+
+    /// Check the context validity without blocking, the validity before the update is used while
+    /// a [`ContextGuard`](struct.ContextGuard.html) holds the context.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            ContextSlot::Owned(cell) => cell.is_valid(),
+            ContextSlot::Unwrapping(fut, state) => match fut.try_read() {
+                Some(cell) => cell.is_valid(),
+                None => state.is_valid(),
+            },
+            ContextSlot::Unwrapped(context) => context.poll_valid(),
+            ContextSlot::Taken => false,
+        }
+    }
+
+    /// Update the context of a running contract, pending until the handles reading it drop.
+    pub(crate) fn poll_update<R>(&self, cx: &mut Context, f: impl FnOnce(&mut C) -> R) -> Poll<R> {
+        match self {
+            ContextSlot::Owned(cell) => {
+                let mut cell = ready!(cell.poll_write(cx));
+                Poll::Ready(f(&mut cell.context))
+            }
+            _ => panic!("Cannot update a context after expiration"),
+        }
+    }
+
+    /// Get a handle to the context, handles can no longer be upgraded once unwrapping started.
+    pub(crate) fn handle(&self) -> Result<ContextHandle<C>, ContextError> {
+        match self {
+            ContextSlot::Owned(cell) => Ok(ContextHandle::new(cell)),
+            _ => Err(ContextError::from(ContextErrorKind::ExpiredContext)),
+        }
+    }
+
+    /// Lock the handles and wait for the last [`ContextRef`](struct.ContextRef.html) or
+    /// [`ContextGuard`](struct.ContextGuard.html) to drop.
+    pub(crate) fn poll_unwrap(&mut self, cx: &mut Context) -> Poll<()> {
+        if let ContextSlot::Owned(_) = self {
+            if let ContextSlot::Owned(cell) = std::mem::replace(self, ContextSlot::Taken) {
+                *self = cell.into_inner_async();
+            }
+        }
+        if let ContextSlot::Unwrapping(fut, _) = self {
+            let cell = ready!(Pin::new(fut).poll(cx));
+            *self = ContextSlot::Unwrapped(cell.into_inner());
+        }
+        match self {
+            ContextSlot::Unwrapped(_) => Poll::Ready(()),
+            _ => panic!("Cannot poll after expiration"),
+        }
+    }
+
+    /// Take the unwrapped context.
+    pub(crate) fn take(&mut self) -> C {
+        match std::mem::replace(self, ContextSlot::Taken) {
+            ContextSlot::Unwrapped(context) => context,
+            _ => panic!("Cannot poll after expiration"),
+        }
+    }
+
+    pub(crate) fn poll_into_inner(&mut self, cx: &mut Context) -> Poll<C> {
+        ready!(self.poll_unwrap(cx));
+        Poll::Ready(self.take())
+    }
+
+    pub(crate) fn is_taken(&self) -> bool {
+        matches!(self, ContextSlot::Taken)
+    }
+}
+
+/// Thread-safe handle to the context of a contract.
+///
+/// Upgrade it into a shared [`ContextRef`](struct.ContextRef.html) to read the context, or into an
+/// exclusive [`ContextGuard`](struct.ContextGuard.html) to update it, for as long as they are alive.
+pub struct ContextHandle<C> {
+    weak: RwLockWeak<ContextCell<C>>,
+}
+
+impl<C> ContextHandle<C>
+where
+    C: ContractContext,
+{
+    pub(crate) fn new(parent: &RwParentArc<ContextCell<C>>) -> Self {
+        Self {
+            weak: RwParentArc::downgrade(parent),
+        }
+    }
+
+    /// Read the context if the contract has not consumed it yet, waiting for a writer to drop.
+    ///
+    /// Readers share the context with each other and with the validity checks of the contract.
+    pub fn read(&self) -> Option<ContextRef<C>> {
+        self.weak.read().ok().map(ContextRef::new)
+    }
+
+    /// Get exclusive access to the context if the contract has not consumed it yet, waiting for
+    /// the readers to drop.
+    ///
+    /// The contract is woken when the guard drops if the update changed the context validity.
+    pub fn write(&self) -> Option<ContextGuard<C>> {
+        self.weak.write().ok().map(ContextGuard::new)
+    }
+}
+
+// Implemented by hand, cloning a handle does not clone the context
+impl<C> Clone for ContextHandle<C> {
+    fn clone(&self) -> Self {
+        Self {
+            weak: self.weak.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for ContextHandle<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(ContextHandle)")
+    }
+}
+
+/// Handles are equal if they refer to the context of the same contract.
+impl<C> PartialEq for ContextHandle<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.weak == other.weak
+    }
+}
+
+impl<C> Eq for ContextHandle<C> {}
+
+impl<C> Hash for ContextHandle<C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.weak.hash(state)
+    }
+}
+
+/// Shared reference to the context of a running contract, the owning contract is notified on drop
+/// if it waited for it.
+pub struct ContextRef<C> {
+    child: ManuallyDrop<ReadChildArc<ContextCell<C>>>,
+    state: Arc<CellState>,
+}
+
+impl<C> ContextRef<C> {
+    fn new(child: ReadChildArc<ContextCell<C>>) -> Self {
+        Self {
+            state: child.state.clone(),
+            child: ManuallyDrop::new(child),
+        }
+    }
+}
+
+impl<C> ops::Deref for ContextRef<C> {
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.child.context
+    }
+}
+
+impl<C> Drop for ContextRef<C> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.child) };
+        self.state.released();
+    }
+}
+
+/// Exclusive access to a context, the owning contract is notified on drop if its validity changed
+/// or if it waited for the guard.
+pub struct ContextGuard<C>
+where
+    C: ContractContext,
+{
+    child: ManuallyDrop<WriteChildArc<ContextCell<C>>>,
+    state: Arc<CellState>,
+    valid: bool,
+}
+
+impl<C> ContextGuard<C>
+where
+    C: ContractContext,
+{
+    fn new(child: WriteChildArc<ContextCell<C>>) -> Self {
+        Self {
+            valid: child.is_valid(),
+            state: child.state.clone(),
+            child: ManuallyDrop::new(child),
+        }
+    }
+}
+
+impl<C> ops::Deref for ContextGuard<C>
+where
+    C: ContractContext,
+{
+    type Target = C;
+    fn deref(&self) -> &Self::Target {
+        &self.child.context
+    }
+}
+
+impl<C> ops::DerefMut for ContextGuard<C>
+where
+    C: ContractContext,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.child.context
+    }
+}
+
+impl<C> Drop for ContextGuard<C>
+where
+    C: ContractContext,
+{
+    fn drop(&mut self) {
+        let changed = self.child.is_valid() != self.valid;
+
+        // Release the context before waking the contract so it can read it
+        unsafe { ManuallyDrop::drop(&mut self.child) };
+        if changed {
+            self.state.waker.wake();
+        }
+        self.state.released();
+    }
+}
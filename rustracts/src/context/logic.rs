@@ -0,0 +1,181 @@
+use super::ContractContext;
+
+/// Boxed context that can be moved to the thread of a contract and read by its handles
+pub type BoxContext = Box<dyn ContractContext + Send + Sync>;
+
+/// Context valid while both contexts are valid
+pub struct AndContext<A, B>(pub A, pub B);
+
+impl<A, B> ContractContext for AndContext<A, B>
+where
+    A: ContractContext,
+    B: ContractContext,
+{
+    fn poll_valid(&self) -> bool {
+        self.0.poll_valid() && self.1.poll_valid()
+    }
+}
+
+/// Context valid while any of the contexts is valid
+pub struct OrContext<A, B>(pub A, pub B);
+
+impl<A, B> ContractContext for OrContext<A, B>
+where
+    A: ContractContext,
+    B: ContractContext,
+{
+    fn poll_valid(&self) -> bool {
+        self.0.poll_valid() || self.1.poll_valid()
+    }
+}
+
+/// Context valid while exactly one of the contexts is valid
+pub struct XorContext<A, B>(pub A, pub B);
+
+impl<A, B> ContractContext for XorContext<A, B>
+where
+    A: ContractContext,
+    B: ContractContext,
+{
+    fn poll_valid(&self) -> bool {
+        self.0.poll_valid() != self.1.poll_valid()
+    }
+}
+
+/// Context valid while the inner context is not
+pub struct NotContext<A>(pub A);
+
+impl<A> ContractContext for NotContext<A>
+where
+    A: ContractContext,
+{
+    fn poll_valid(&self) -> bool {
+        !self.0.poll_valid()
+    }
+}
+
+/// Context valid while all of its contexts are valid, valid when empty
+#[derive(Default)]
+pub struct AllContext(pub Vec<BoxContext>);
+
+impl AllContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a context to the clauses.
+    pub fn with<C>(mut self, context: C) -> Self
+    where
+        C: ContractContext + Send + Sync + 'static,
+    {
+        self.0.push(Box::new(context));
+        self
+    }
+}
+
+impl ContractContext for AllContext {
+    fn poll_valid(&self) -> bool {
+        self.0.iter().all(|c| c.poll_valid())
+    }
+}
+
+/// Context valid while any of its contexts is valid, invalid when empty
+#[derive(Default)]
+pub struct AnyContext(pub Vec<BoxContext>);
+
+impl AnyContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a context to the clauses.
+    pub fn with<C>(mut self, context: C) -> Self
+    where
+        C: ContractContext + Send + Sync + 'static,
+    {
+        self.0.push(Box::new(context));
+        self
+    }
+}
+
+impl ContractContext for AnyContext {
+    fn poll_valid(&self) -> bool {
+        self.0.iter().any(|c| c.poll_valid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllContext, AnyContext};
+    use crate::context::cmp::{EqContext, GtContext};
+    use crate::context::{ContractContext, ContractContextExt};
+    use crate::time::{MockClock, Timer};
+    use crate::{ContractExt, FuturesContract, Status};
+
+    use std::time::Duration;
+
+    #[test]
+    fn logic_contexts() {
+        assert!(true.and(true).poll_valid());
+        assert!(!true.and(false).poll_valid());
+        assert!(false.or(true).poll_valid());
+        assert!(!false.or(false).poll_valid());
+        assert!(true.xor(false).poll_valid());
+        assert!(!true.xor(true).poll_valid());
+        assert!(false.not().poll_valid());
+        assert!(EqContext(1, 1)
+            .and(GtContext(2, 1).not())
+            .not()
+            .poll_valid());
+    }
+
+    #[test]
+    fn all_any_contexts() {
+        assert!(AllContext::new().poll_valid());
+        assert!(!AnyContext::new().poll_valid());
+
+        let all = AllContext::new().with(true).with(EqContext(2, 2));
+        assert!(all.poll_valid());
+        assert!(!all.with(false).poll_valid());
+
+        let any = AnyContext::new().with(false).with(EqContext(2, 3));
+        assert!(!any.poll_valid());
+        assert!(any.with(GtContext(3, 2)).poll_valid());
+    }
+
+    #[test]
+    fn local_contexts() {
+        // Contexts do not have to be Send to be combined
+        struct Local(std::rc::Rc<bool>);
+
+        impl ContractContext for Local {
+            fn poll_valid(&self) -> bool {
+                *self.0
+            }
+        }
+
+        let flag = std::rc::Rc::new(true);
+        assert!(Local(flag.clone()).and(true).poll_valid());
+        assert!(!Local(flag).not().poll_valid());
+    }
+
+    #[test]
+    fn and_voided_contract() {
+        let context = GtContext(3, 2).and(EqContext(1, 1)); // Valid while both clauses are met
+
+        let clock = MockClock::new();
+        let c = FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(1), clock.clone()),
+            context,
+            |con| -> usize { con.0 .0 + con.1 .0 },
+        );
+
+        let mcontext = c.get_context().unwrap();
+        if let Some(mut context) = mcontext.write() {
+            context.1 .0 = 2; // Break the second clause only
+        }
+        clock.advance(Duration::from_secs(1));
+
+        assert!(matches!(crate::executor::block_on(c), Status::Voided(_)));
+    }
+}
@@ -0,0 +1,282 @@
+//! Contexts are elements that can be polled to verify wether their inner state is still considered
+//! valid or not.
+
+mod handle;
+
+pub(crate) use handle::ContextSlot;
+pub use handle::{ContextGuard, ContextHandle, ContextRef};
+
+/// Logical combinations of contexts
+pub mod logic;
+
+/// Trait for Contexts
+pub trait ContractContext {
+    /// Check wether the clauses are still met, true by default.
+    fn poll_valid(&self) -> bool {
+        true
+    }
+}
+
+/// Extention trait to combine Contexts.
+pub trait ContractContextExt: ContractContext + Sized {
+    /// Context valid while both contexts are valid.
+    fn and<B>(self, other: B) -> logic::AndContext<Self, B>
+    where
+        B: ContractContext,
+    {
+        logic::AndContext(self, other)
+    }
+
+    /// Context valid while any of the contexts is valid.
+    fn or<B>(self, other: B) -> logic::OrContext<Self, B>
+    where
+        B: ContractContext,
+    {
+        logic::OrContext(self, other)
+    }
+
+    /// Context valid while exactly one of the contexts is valid.
+    fn xor<B>(self, other: B) -> logic::XorContext<Self, B>
+    where
+        B: ContractContext,
+    {
+        logic::XorContext(self, other)
+    }
+
+    /// Context valid while this context is not.
+    fn not(self) -> logic::NotContext<Self> {
+        logic::NotContext(self)
+    }
+}
+
+impl<C> ContractContextExt for C where C: ContractContext {}
+
+impl<C> ContractContext for Box<C>
+where
+    C: ContractContext + ?Sized,
+{
+    fn poll_valid(&self) -> bool {
+        (**self).poll_valid()
+    }
+}
+
+/// Kinds of ContextErrors
+#[derive(Debug)]
+pub enum ContextErrorKind {
+    /// Context has expired for this current Contract
+    ExpiredContext,
+}
+
+/// Error Type for Context Related Errors
+#[derive(Debug)]
+pub struct ContextError {
+    kind: ContextErrorKind,
+}
+
+impl ContextError {
+    /// Build a ContextError from a ContextErrorKind
+    pub fn from(kind: ContextErrorKind) -> Self {
+        Self { kind }
+    }
+}
+
+impl std::fmt::Display for ContextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ContextErrorKind::*;
+        let error = match &self.kind {
+            k @ ExpiredContext => {
+                format!("{:?}: context is no longer available in this contract", k)
+            }
+        };
+        write!(f, "{}", error)
+    }
+}
+
+impl std::error::Error for ContextError {}
+
+impl ContractContext for bool {
+    fn poll_valid(&self) -> bool {
+        *self
+    }
+}
+
+impl ContractContext for () {}
+
+impl ContractContext for u8 {}
+impl ContractContext for u16 {}
+impl ContractContext for u32 {}
+impl ContractContext for u64 {}
+impl ContractContext for u128 {}
+impl ContractContext for usize {}
+
+impl ContractContext for i8 {}
+impl ContractContext for i16 {}
+impl ContractContext for i32 {}
+impl ContractContext for i64 {}
+impl ContractContext for i128 {}
+impl ContractContext for isize {}
+
+/// Empty context to use if you want non-voidable contracts that produce no value.
+#[derive(Copy, Clone)]
+pub struct DefaultContext;
+
+impl ContractContext for DefaultContext {}
+
+impl Default for DefaultContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DefaultContext {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Generic comparaison contexts
+pub mod cmp {
+    use super::ContractContext;
+
+    /// Context to compare the equality of two elements
+    pub struct EqContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for EqContext<A, B>
+    where
+        A: PartialEq<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 == self.1
+        }
+    }
+
+    /// Context to compare the inequality of two elements
+    pub struct NqContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for NqContext<A, B>
+    where
+        A: PartialEq<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 != self.1
+        }
+    }
+
+    /// Context to compare the less than ordering of two elements
+    pub struct LtContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for LtContext<A, B>
+    where
+        A: PartialOrd<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 < self.1
+        }
+    }
+
+    /// Context to compare the less or equal ordering of two elements
+    pub struct LeContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for LeContext<A, B>
+    where
+        A: PartialOrd<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 <= self.1
+        }
+    }
+
+    /// Context to compare the greater than ordering of two elements
+    pub struct GtContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for GtContext<A, B>
+    where
+        A: PartialOrd<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 > self.1
+        }
+    }
+
+    /// Context to compare the greater or equal ordering of two elements
+    pub struct GeContext<A, B>(pub A, pub B);
+
+    impl<A, B> ContractContext for GeContext<A, B>
+    where
+        A: PartialOrd<B>,
+    {
+        fn poll_valid(&self) -> bool {
+            self.0 >= self.1
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use proptest::prelude::*;
+        use std::net::{IpAddr, Ipv4Addr};
+
+        proptest! {
+            #[test]
+            fn eq_nq_contexts(a: i64, b: i64) {
+                prop_assert_eq!(EqContext(a, b).poll_valid(), a == b);
+                prop_assert_eq!(NqContext(a, b).poll_valid(), a != b);
+                prop_assert!(EqContext(a, a).poll_valid());
+                prop_assert!(!NqContext(a, a).poll_valid());
+            }
+
+            #[test]
+            fn ordering_contexts(a: i64, b: i64) {
+                prop_assert_eq!(LtContext(a, b).poll_valid(), a < b);
+                prop_assert_eq!(LeContext(a, b).poll_valid(), a <= b);
+                prop_assert_eq!(GtContext(a, b).poll_valid(), a > b);
+                prop_assert_eq!(GeContext(a, b).poll_valid(), a >= b);
+            }
+
+            #[test]
+            fn ordering_contexts_at_equality(a: i64) {
+                prop_assert!(LeContext(a, a).poll_valid());
+                prop_assert!(GeContext(a, a).poll_valid());
+                prop_assert!(!LtContext(a, a).poll_valid());
+                prop_assert!(!GtContext(a, a).poll_valid());
+            }
+
+            #[test]
+            fn ordering_contexts_are_dual(a: i64, b: i64) {
+                prop_assert_eq!(LtContext(a, b).poll_valid(), GtContext(b, a).poll_valid());
+                prop_assert_eq!(LeContext(a, b).poll_valid(), GeContext(b, a).poll_valid());
+                prop_assert_ne!(LtContext(a, b).poll_valid(), GeContext(a, b).poll_valid());
+                prop_assert_ne!(GtContext(a, b).poll_valid(), LeContext(a, b).poll_valid());
+            }
+
+            #[test]
+            fn float_contexts(a in proptest::num::f64::ANY, b in proptest::num::f64::ANY) {
+                prop_assert_eq!(EqContext(a, b).poll_valid(), a == b);
+                prop_assert_eq!(LtContext(a, b).poll_valid(), a < b);
+                prop_assert_eq!(LeContext(a, b).poll_valid(), a <= b);
+                prop_assert_eq!(GtContext(a, b).poll_valid(), a > b);
+                prop_assert_eq!(GeContext(a, b).poll_valid(), a >= b);
+            }
+
+            #[test]
+            fn mixed_type_contexts(a: u32, b: u32) {
+                let (addr, ip) = (Ipv4Addr::from(a), IpAddr::V4(Ipv4Addr::from(b)));
+                prop_assert_eq!(EqContext(addr, ip).poll_valid(), a == b);
+                prop_assert_eq!(LtContext(addr, ip).poll_valid(), a < b);
+                prop_assert_eq!(LeContext(addr, ip).poll_valid(), a <= b);
+                prop_assert_eq!(GtContext(addr, ip).poll_valid(), a > b);
+                prop_assert_eq!(GeContext(addr, ip).poll_valid(), a >= b);
+            }
+        }
+
+        #[test]
+        fn nan_contexts() {
+            // Ordering is partial, every comparison with NaN is invalid
+            assert!(!GeContext(f64::NAN, f64::NAN).poll_valid());
+            assert!(!LeContext(1.0, f64::NAN).poll_valid());
+            assert!(!EqContext(f64::NAN, f64::NAN).poll_valid());
+            assert!(NqContext(f64::NAN, f64::NAN).poll_valid());
+        }
+    }
+}
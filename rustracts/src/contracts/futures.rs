@@ -1,31 +1,34 @@
-use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::context::{ContextError, ContextErrorKind, ContractContext};
-use crate::park::{WaitMessage, WaitThread};
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextHandle, ContextSlot, ContractContext};
 use crate::time::Timer;
-use crate::{Contract, ContractExt, Status};
+use crate::{Contract, ContractExt, ContractHandle, Status};
 
 use futures::{
     future::{FusedFuture, Future},
+    ready,
     task::{Context, Poll},
 };
-use parc::{LockWeak, ParentArc};
+use pin_project_lite::pin_project;
 
-/// A FuturesContract produces a value from it's context at it's expire time if it has not been voided
-/// before.
-#[must_use = "contracts do nothing unless polled or awaited"]
-pub struct FuturesContract<F, C, R>
-where
-    C: ContractContext,
-    F: FnOnce(C) -> R,
-{
-    runner: WaitThread,
-    timer: Timer,
+pin_project! {
+    /// A FuturesContract produces a value from it's context at it's expire time if it has not been voided
+    /// before.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct FuturesContract<F, C, R>
+    where
+        C: ContractContext,
+        F: FnOnce(C) -> R,
+    {
+        timer: Timer,
 
-    context: Option<ParentArc<Mutex<C>>>,
+        context: ContextSlot<C>,
 
-    on_exe: Option<F>,
+        on_exe: Option<F>,
+
+        cancel: Cancel,
+    }
 }
 
 impl<F, C, R> FuturesContract<F, C, R>
@@ -34,17 +37,29 @@ where
     F: FnOnce(C) -> R,
 {
     pub fn new(expire: Duration, context: C, on_exe: F) -> Self {
+        Self::with_timer(Timer::new(expire), context, on_exe)
+    }
+
+    /// Build a contract expiring at `deadline`.
+    pub fn new_at(deadline: Instant, context: C, on_exe: F) -> Self {
+        Self::with_timer(Timer::at(deadline), context, on_exe)
+    }
+
+    /// Build a contract expiring once the wall-clock time reaches `deadline`, use it for contracts
+    /// restored from storage.
+    pub fn new_at_system_time(deadline: SystemTime, context: C, on_exe: F) -> Self {
+        Self::with_timer(Timer::at(deadline), context, on_exe)
+    }
+
+    /// Build a contract expiring with `timer`, use it to follow another [`Clock`](../time/trait.Clock.html).
+    pub fn with_timer(timer: Timer, context: C, on_exe: F) -> Self {
         Self {
-            runner: WaitThread::new(),
-            timer: Timer::new(expire),
-            context: Some(ParentArc::new(Mutex::new(context))),
+            timer,
+            context: ContextSlot::new(context),
             on_exe: Some(on_exe),
+            cancel: Cancel::new(),
         }
     }
-
-    pin_utils::unsafe_pinned!(timer: Timer);
-    pin_utils::unsafe_unpinned!(on_exe: Option<F>);
-    pin_utils::unsafe_unpinned!(context: Option<ParentArc<Mutex<C>>>);
 }
 
 impl<F, C, R> Contract for FuturesContract<F, C, R>
@@ -53,33 +68,41 @@ where
     F: FnOnce(C) -> R,
 {
     fn poll_valid(&self) -> bool {
-        match &self.context {
-            Some(c) => c.as_ref().lock().unwrap().poll_valid(),
-            None => false,
-        }
+        self.context.is_valid()
     }
 
-    fn execute(mut self: std::pin::Pin<&mut Self>) -> Self::Output {
-        let lockarc = self
-            .as_mut()
-            .context()
-            .take()
-            .expect("Cannot poll after return");
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.timer.remaining())
+    }
 
-        // Consumme ParentArc to return the mutex
-        let context = lockarc.block_into_inner().into_inner().unwrap();
+    fn poll_execute(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
 
-        let f = self
-            .as_mut()
-            .on_exe()
-            .take()
-            .expect("Cannot poll after return");
+        // Wait for the readers to drop to return the context
+        let context = ready!(this.context.poll_into_inner(cx));
 
-        Status::Completed(f(context))
+        let f = this.on_exe.take().expect("Cannot poll after return");
+
+        this.cancel
+            .finish(Poll::Ready(Status::Completed(f(context))))
     }
 
-    fn void(self: std::pin::Pin<&mut Self>) -> Self::Output {
-        Status::Terminated
+    fn poll_void(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+
+        // Hand the context back instead of dropping it
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_exe.take();
+
+        this.cancel.finish(Poll::Ready(Status::Voided(context)))
+    }
+
+    fn poll_cancel(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_exe.take();
+
+        this.cancel.finish(Poll::Ready(Status::Cancelled(context)))
     }
 }
 
@@ -88,13 +111,14 @@ where
     C: ContractContext,
     F: FnOnce(C) -> R,
 {
-    type Context = LockWeak<Mutex<C>>;
+    type Context = ContextHandle<C>;
 
     fn get_context(&self) -> Result<Self::Context, ContextError> {
-        match &self.context {
-            Some(ref c) => Ok(ParentArc::downgrade(c)),
-            None => Err(ContextError::from(ContextErrorKind::ExpiredContext)),
-        }
+        self.context.handle()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
     }
 }
 
@@ -103,22 +127,25 @@ where
     C: ContractContext,
     F: FnOnce(C) -> R,
 {
-    type Output = Status<R>;
+    type Output = Status<R, C>;
 
     fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.runner
-            .sender()
-            .send(WaitMessage::WakeIn {
-                waker: cx.waker().clone(),
-                duration: Duration::new(0, 1000),
-            })
-            .unwrap();
+        if self.cancel.poll_cancelled(cx) {
+            return self.as_mut().poll_cancel(cx);
+        }
 
-        let mv = (self.as_mut().timer().poll(cx), self.poll_valid());
+        // Updates through a ContextHandle wake the task if they invalidate the context, it is
+        // checked again until the readers alive at termination have dropped
+        let valid = ready!(self.context.poll_valid(cx));
+
+        let mv = (
+            std::pin::Pin::new(self.as_mut().project().timer).poll(cx),
+            valid,
+        );
         match mv {
-            (Poll::Ready(_), true) => Poll::Ready(self.execute()),
+            (Poll::Ready(_), true) => self.as_mut().poll_execute(cx),
             (Poll::Pending, true) => Poll::Pending,
-            (_, false) => Poll::Ready(self.void()),
+            (_, false) => self.as_mut().poll_void(cx),
         }
     }
 }
@@ -129,23 +156,32 @@ where
     F: FnOnce(C) -> R,
 {
     fn is_terminated(&self) -> bool {
-        self.context.is_none() || self.on_exe.is_none()
+        self.context.is_taken() || self.on_exe.is_none()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{context::cmp::GtContext, ContractExt, FuturesContract, Status};
+    use crate::time::{Clock, MockClock, Timer};
+    use crate::{context::cmp::GtContext, Contract, ContractExt, FuturesContract, Status};
+    use futures::task::Poll;
     use std::time::Duration;
 
+    fn mock_timer(expire: Duration) -> (MockClock, Timer) {
+        let clock = MockClock::new();
+        (clock.clone(), Timer::with_clock(expire, clock))
+    }
+
     #[test]
     fn fut_simple_contract() {
-        let c = FuturesContract::new(Duration::from_secs(1), (), |_| -> usize { 5 });
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, (), |_| -> usize { 5 });
+        clock.advance(Duration::from_secs(1));
 
-        if let Status::Completed(value) = futures::executor::block_on(c) {
+        if let Status::Completed(value) = crate::executor::block_on(c) {
             assert_eq!(value, 5)
         } else {
-            assert!(false)
+            unreachable!();
         }
     }
 
@@ -153,50 +189,98 @@ mod tests {
     fn fut_voided_contract() {
         let context = GtContext(3, 2); // Context is true while self.0 > self.1
 
-        let c = FuturesContract::new(Duration::from_secs(4), context, |con| -> usize {
-            con.0 + 5
-        });
+        let (clock, timer) = mock_timer(Duration::from_secs(4));
+        let c = FuturesContract::with_timer(timer, context, |con| -> usize { con.0 + 5 });
 
         let _ = std::thread::spawn({
             let mcontext = c.get_context().unwrap();
             move || {
-                match mcontext.upgrade() {
-                    Some(strong) => strong.lock().unwrap().0 = 1, // Modify context before contract ends
-                    None => {}
+                if let Some(mut context) = mcontext.write() {
+                    context.0 = 1; // Modify context before contract ends
                 }
                 // mcontext goes out of scope so strong count goes back to one
             }
         })
         .join();
+        clock.advance(Duration::from_secs(4));
 
-        if let Status::Completed(val) = futures::executor::block_on(c) {
+        if let Status::Completed(val) = crate::executor::block_on(c) {
             assert_ne!(val, 1);
-        } else {
-            assert!(true); // Contract should be voided because updated value is 1 which is < 2
         }
     }
 
+    #[test]
+    fn fut_voided_before_expiration() {
+        let context = GtContext(3, 2); // Context is true while self.0 > self.1
+
+        let (_clock, timer) = mock_timer(Duration::from_secs(60));
+        let c = FuturesContract::with_timer(timer, context, |con| -> usize { con.0 + 5 });
+
+        let handle = std::thread::spawn({
+            let mcontext = c.get_context().unwrap();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                if let Some(mut context) = mcontext.write() {
+                    context.0 = 1; // Void the context while the contract is awaited
+                }
+            }
+        });
+
+        // The clock never moves, only the update can wake the contract which returns its context
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Voided(GtContext(1, 2))
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn fut_cloned_context_handles() {
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, 0, |con| -> usize { con });
+
+        let handle = c.get_context().unwrap();
+        assert_eq!(handle, c.get_context().unwrap());
+
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let mcontext = handle.clone();
+                std::thread::spawn(move || {
+                    if let Some(mut context) = mcontext.write() {
+                        *context += 1;
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().for_each(|w| w.join().unwrap());
+        clock.advance(Duration::from_secs(1));
+
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(2)));
+        assert!(handle.read().is_none());
+    }
+
     #[test]
     fn fut_updated_contract() {
         let context = GtContext(3, 2); // Context is valid while self.0 > self.1
 
-        let c = FuturesContract::new(Duration::from_secs(1), context, |con| -> usize {
-            con.0 + 5
-        });
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, context, |con| -> usize { con.0 + 5 });
 
         let _ = std::thread::spawn({
             let mcontext = c.get_context().unwrap();
-            move || match mcontext.upgrade() {
-                Some(strong) => strong.lock().unwrap().0 += 2,
-                None => {}
+            move || {
+                if let Some(mut context) = mcontext.write() {
+                    context.0 += 2;
+                }
             }
         })
         .join();
+        clock.advance(Duration::from_secs(1));
 
-        if let Status::Completed(value) = futures::executor::block_on(c) {
+        if let Status::Completed(value) = crate::executor::block_on(c) {
             assert_eq!(value, 10);
         } else {
-            assert!(false);
+            unreachable!();
         }
     }
 
@@ -204,27 +288,262 @@ mod tests {
     fn loop_fut_updated_contract() {
         let context = GtContext(3, 2); // Context is valid while self.0 > self.1
 
-        let c = FuturesContract::new(Duration::from_secs(1), context, |con| -> usize {
-            con.0 + 5
-        });
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, context, |con| -> usize { con.0 + 5 });
 
         let handle = std::thread::spawn({
             let mcontext = c.get_context().unwrap();
-            move || loop {
-                match mcontext.upgrade() {
-                    Some(strong) => {
-                        strong.lock().unwrap().0;
-                    }
-                    None => break,
+            move || {
+                while let Some(context) = mcontext.read() {
+                    let _ = context.0;
                 }
             }
         });
+        let ticker = std::thread::spawn(move || clock.advance(Duration::from_secs(1)));
 
-        if let Status::Completed(_) = futures::executor::block_on(c) {
-            assert!(true);
-        } else {
-            assert!(false);
-        }
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(_)));
         let _ = handle.join();
+        ticker.join().unwrap();
+    }
+
+    #[test]
+    fn fut_reader_on_same_thread() {
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, 3, |con| -> usize { con + 5 });
+
+        let reader = c.get_context().unwrap().read().unwrap();
+        clock.advance(Duration::from_secs(1));
+
+        // The expired contract waits for the reader polled on the same thread without blocking it
+        let (status, _) = crate::executor::block_on(futures::future::join(c, async move {
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    return futures::task::Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                futures::task::Poll::Pending
+            })
+            .await;
+            drop(reader);
+        }));
+
+        assert!(matches!(status, Status::Completed(8)));
+    }
+
+    #[test]
+    fn fut_guard_held_across_await() {
+        let (_clock, timer) = mock_timer(Duration::from_secs(60));
+        let c = FuturesContract::with_timer(timer, GtContext(3, 2), |con| -> usize { con.0 });
+        let mut guard = c.get_context().unwrap().write().unwrap();
+
+        // Polling the contract does not block the executor thread the guard is held on
+        let (status, _) = crate::executor::block_on(futures::future::join(c, async move {
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    return futures::task::Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                futures::task::Poll::Pending
+            })
+            .await;
+            guard.0 = 1;
+            drop(guard);
+        }));
+
+        assert!(matches!(status, Status::Voided(GtContext(1, 2))));
+    }
+
+    #[test]
+    fn fut_dropped_with_reader_on_same_thread() {
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let mut c = FuturesContract::with_timer(timer, 3, |con| -> usize { con + 5 });
+        let handle = c.get_context().unwrap();
+
+        // Dropping the contract while it waits for the reader does not wait for it
+        let reader = handle.read().unwrap();
+        clock.advance(Duration::from_secs(1));
+        crate::executor::block_on(async {
+            assert!(futures::poll!(&mut c).is_pending());
+        });
+        drop(c);
+        assert!(handle.read().is_none());
+        assert_eq!(*reader, 3);
+
+        // Neither does dropping a running contract
+        let c = FuturesContract::new(Duration::from_secs(3600), 4, |con| -> usize { con });
+        let reader = c.get_context().unwrap().read().unwrap();
+        drop(c);
+        assert_eq!(*reader, 4);
+    }
+
+    #[test]
+    fn fut_local_context() {
+        // Contexts that are not Send are owned by a contract driven on their thread
+        struct Local(std::rc::Rc<bool>);
+
+        impl crate::ContractContext for Local {
+            fn poll_valid(&self) -> bool {
+                *self.0
+            }
+        }
+
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, Local(std::rc::Rc::new(true)), |con| *con.0);
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Completed(true)
+        ));
+
+        // A dropped contract gives up its context to the last reader
+        let flag = std::rc::Rc::new(true);
+        let c = FuturesContract::new(Duration::from_secs(3600), Local(flag.clone()), |con| *con.0);
+        let reader = c.get_context().unwrap().read().unwrap();
+        drop(c);
+        assert_eq!(std::rc::Rc::strong_count(&flag), 2);
+        drop(reader);
+        assert_eq!(std::rc::Rc::strong_count(&flag), 1);
+    }
+
+    #[test]
+    fn fut_cancelled_contract() {
+        let (_clock, timer) = mock_timer(Duration::from_secs(60));
+        let c = FuturesContract::with_timer(timer, GtContext(3, 2), |con| -> usize { con.0 });
+
+        let handle = c.get_handle();
+        let canceller = std::thread::spawn({
+            let handle = handle.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                handle.cancel(); // Wakes the contract although the clock never moves
+            }
+        });
+        assert!(!handle.is_finished());
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Cancelled(GtContext(3, 2))
+        ));
+        assert!(handle.is_cancelled() && handle.is_finished());
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn fut_cancelled_after_completion() {
+        let (clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, (), |_| -> usize { 5 });
+        let handle = c.get_handle();
+        clock.advance(Duration::from_secs(1));
+
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(5)));
+        handle.cancel();
+        assert!(handle.is_finished() && !handle.is_cancelled());
+    }
+
+    #[test]
+    fn fut_cancel_by_hand() {
+        let (_clock, timer) = mock_timer(Duration::from_secs(1));
+        let c = FuturesContract::with_timer(timer, 3, |con| -> usize { con + 5 });
+        let handle = c.get_handle();
+        futures::pin_mut!(c);
+
+        // A combinator cancelling the contract finishes it before dropping it
+        let mut cx = futures::task::Context::from_waker(futures::task::noop_waker_ref());
+        assert!(matches!(
+            c.as_mut().poll_cancel(&mut cx),
+            Poll::Ready(Status::Cancelled(3))
+        ));
+        assert!(handle.is_finished());
+    }
+
+    #[test]
+    fn fut_system_time_contract() {
+        let clock = MockClock::new();
+        let deadline = clock.system_now() + Duration::from_secs(3600);
+        let c = FuturesContract::with_timer(
+            Timer::with_deadline(deadline, clock.clone()),
+            (),
+            |_| -> usize { 5 },
+        );
+
+        // Restored after the system time moved past the deadline while the process was down
+        clock.set_system_time(deadline + Duration::from_secs(1));
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(5)));
+    }
+
+    #[test]
+    fn fut_never_expiring_contract() {
+        let c = FuturesContract::new(Duration::MAX, (), |_| -> usize { 5 });
+        assert_eq!(c.remaining(), Some(Duration::MAX));
+
+        let handle = c.get_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            handle.cancel();
+        });
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Cancelled(())
+        ));
+        canceller.join().unwrap();
+
+        let c = FuturesContract::new(Duration::from_secs(u64::MAX), (), |_| -> usize { 5 });
+        assert_eq!(c.remaining(), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn fut_system_clock_contract() {
+        // Driven by the timers of the enabled runtime feature
+        let c = FuturesContract::new(Duration::from_millis(20), (), |_| -> usize { 5 });
+
+        let start = std::time::Instant::now();
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(5)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn fut_woken_by_clock() {
+        let (clock, timer) = mock_timer(Duration::from_secs(3600));
+        let c = FuturesContract::with_timer(timer, (), |_| -> usize { 5 });
+
+        let ticker = std::thread::spawn(move || {
+            for _ in 0..60 {
+                std::thread::sleep(Duration::from_millis(1));
+                clock.advance(Duration::from_secs(60));
+            }
+        });
+
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(5)));
+        ticker.join().unwrap();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn fut_tokio_paused_contract() {
+        let c = FuturesContract::new(Duration::from_secs(3600), (), |_| -> usize { 5 });
+
+        // Paused time auto-advances to the contract expiration
+        let start = std::time::Instant::now();
+        assert!(matches!(c.await, Status::Completed(5)));
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn fut_tokio_advanced_contract() {
+        let mut c = FuturesContract::new(Duration::from_secs(10), (), |_| -> usize { 5 });
+
+        assert!(futures::poll!(&mut c).is_pending());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(futures::poll!(&mut c).is_pending());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(matches!(
+            futures::poll!(&mut c),
+            Poll::Ready(Status::Completed(5))
+        ));
     }
 }
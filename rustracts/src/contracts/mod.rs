@@ -1,7 +1,11 @@
 mod futures;
 mod onkill;
 mod option;
+mod periodic;
+mod trigger;
 
 pub use self::futures::FuturesContract;
 pub use self::onkill::OnKillContract;
 pub use self::option::OptionContract;
+pub use self::periodic::PeriodicContract;
+pub use self::trigger::TriggerContract;
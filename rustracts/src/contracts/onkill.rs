@@ -1,28 +1,28 @@
-use std::sync::Mutex;
-use std::time::Duration;
-
-use crate::context::{ContextError, ContextErrorKind, ContractContext};
-use crate::park::{WaitMessage, WaitThread};
-use crate::{Contract, ContractExt, Status};
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextHandle, ContextSlot, ContractContext};
+use crate::{Contract, ContractExt, ContractHandle, Status};
 
 use futures::{
     future::{FusedFuture, Future},
+    ready,
     task::{Context, Poll},
 };
-use parc::{LockWeak, ParentArc};
+use pin_project_lite::pin_project;
 
-/// Permanent contract that produces a value when it is voided by the underlying context.
-#[must_use = "contracts do nothing unless polled or awaited"]
-pub struct OnKillContract<F, C, R>
-where
-    C: ContractContext,
-    F: FnOnce(C) -> R,
-{
-    runner: WaitThread,
+pin_project! {
+    /// Permanent contract that produces a value when it is voided by the underlying context.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct OnKillContract<F, C, R>
+    where
+        C: ContractContext,
+        F: FnOnce(C) -> R,
+    {
+        context: ContextSlot<C>,
 
-    context: Option<ParentArc<Mutex<C>>>,
+        on_void: Option<F>,
 
-    on_void: Option<F>,
+        cancel: Cancel,
+    }
 }
 
 impl<F, C, R> OnKillContract<F, C, R>
@@ -32,14 +32,11 @@ where
 {
     pub fn new(context: C, on_void: F) -> Self {
         Self {
-            runner: WaitThread::new(),
-            context: Some(ParentArc::new(Mutex::new(context))),
+            context: ContextSlot::new(context),
             on_void: Some(on_void),
+            cancel: Cancel::new(),
         }
     }
-
-    pin_utils::unsafe_unpinned!(context: Option<ParentArc<Mutex<C>>>);
-    pin_utils::unsafe_unpinned!(on_void: Option<F>);
 }
 
 impl<F, C, R> Contract for OnKillContract<F, C, R>
@@ -48,32 +45,34 @@ where
     F: FnOnce(C) -> R,
 {
     fn poll_valid(&self) -> bool {
-        match &self.context {
-            Some(c) => c.as_ref().lock().unwrap().poll_valid(),
-            None => false,
-        }
+        self.context.is_valid()
     }
 
-    fn execute(self: std::pin::Pin<&mut Self>) -> Self::Output {
-        Status::Terminated
+    fn poll_execute(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_void.take();
+
+        this.cancel.finish(Poll::Ready(Status::Expired(context)))
     }
 
     // This contract is bound and cannot be voided
-    fn void(mut self: std::pin::Pin<&mut Self>) -> Self::Output {
-        let lockarc = self
-            .as_mut()
-            .context()
-            .take()
-            .expect("Cannot poll after expiration");
-        let context = lockarc.block_into_inner().into_inner().unwrap();
-
-        let f = self
-            .as_mut()
-            .on_void()
-            .take()
-            .expect("Cannot poll after expiration");
-
-        Status::Completed(f(context))
+    fn poll_void(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+
+        let f = this.on_void.take().expect("Cannot poll after expiration");
+
+        this.cancel
+            .finish(Poll::Ready(Status::Completed(f(context))))
+    }
+
+    fn poll_cancel(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_void.take();
+
+        this.cancel.finish(Poll::Ready(Status::Cancelled(context)))
     }
 }
 
@@ -82,13 +81,14 @@ where
     C: ContractContext,
     F: FnOnce(C) -> R,
 {
-    type Context = LockWeak<Mutex<C>>;
+    type Context = ContextHandle<C>;
 
     fn get_context(&self) -> Result<Self::Context, ContextError> {
-        match &self.context {
-            Some(ref c) => Ok(ParentArc::downgrade(c)),
-            None => Err(ContextError::from(ContextErrorKind::ExpiredContext)),
-        }
+        self.context.handle()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
     }
 }
 
@@ -97,19 +97,18 @@ where
     C: ContractContext,
     F: FnOnce(C) -> R,
 {
-    type Output = Status<R>;
-
-    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.runner
-            .sender()
-            .send(WaitMessage::WakeIn {
-                waker: cx.waker().clone(),
-                duration: Duration::new(0, 100),
-            })
-            .unwrap();
-
-        if !self.poll_valid() {
-            Poll::Ready(self.void())
+    type Output = Status<R, C>;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.cancel.poll_cancelled(cx) {
+            return self.as_mut().poll_cancel(cx);
+        }
+
+        // Updates through a ContextHandle wake the task if they invalidate the context
+        let valid = ready!(self.context.poll_valid(cx));
+
+        if !valid {
+            self.as_mut().poll_void(cx)
         } else {
             Poll::Pending
         }
@@ -122,7 +121,7 @@ where
     F: FnOnce(C) -> R,
 {
     fn is_terminated(&self) -> bool {
-        self.context.is_none() || self.on_void.is_none()
+        self.context.is_taken() || self.on_void.is_none()
     }
 }
 
@@ -141,18 +140,62 @@ mod tests {
         let _ = std::thread::spawn({
             let mcontext = c.get_context().unwrap();
             move || {
-                match mcontext.upgrade() {
-                    Some(mutex) => mutex.lock().unwrap().0 = 5, // Modify Context
-                    None => {}
+                if let Some(mut context) = mcontext.write() {
+                    context.0 = 5; // Modify Context
                 }
             }
         })
         .join();
 
-        if let Status::Completed(val) = futures::executor::block_on(c) {
+        if let Status::Completed(val) = crate::executor::block_on(c) {
             assert_eq!(val, 10); // Contract has been executed since context is invalidated by update
         } else {
-            assert!(false);
+            unreachable!();
         }
     }
+
+    #[test]
+    fn okc_woken_by_update() {
+        let context = EqContext(2, 2); // Context which is valid while self.0 == self.1
+
+        let c = OnKillContract::new(context, |con| -> usize { con.0 + 5 });
+
+        let handle = std::thread::spawn({
+            let mcontext = c.get_context().unwrap();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                if let Some(mut context) = mcontext.write() {
+                    context.0 = 5; // Modify Context while the contract is awaited
+                }
+            }
+        });
+
+        if let Status::Completed(val) = crate::executor::block_on(c) {
+            assert_eq!(val, 10);
+        } else {
+            unreachable!();
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn okc_cancelled_contract() {
+        let c = OnKillContract::new(EqContext(2, 2), |con| -> usize { con.0 + 5 });
+
+        let handle = c.get_handle();
+        let canceller = std::thread::spawn({
+            let handle = handle.clone();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                handle.cancel(); // The context stays valid, only the cancellation ends it
+            }
+        });
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Cancelled(EqContext(2, 2))
+        ));
+        assert!(handle.is_finished());
+        canceller.join().unwrap();
+    }
 }
@@ -1,33 +1,36 @@
-use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::context::{ContextError, ContextErrorKind, ContractContext};
-use crate::park::{WaitMessage, WaitThread};
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextHandle, ContextSlot, ContractContext};
 use crate::time::Timer;
-use crate::{Contract, ContractExt, Status};
+use crate::{Contract, ContractExt, ContractHandle, Status};
 
 use futures::{
     future::{FusedFuture, Future},
+    ready,
     task::{Context, Poll},
 };
-use parc::{LockWeak, ParentArc};
-
-/// Contract that produces a value if secondary context is valid at expiration and it has not been
-/// voided by the first context.
-#[must_use = "contracts do nothing unless polled or awaited"]
-pub struct OptionContract<F, VC, PC, R>
-where
-    VC: ContractContext,
-    PC: ContractContext,
-    F: FnOnce((VC, PC)) -> R,
-{
-    runner: WaitThread,
-    timer: Timer,
-
-    void_context: Option<ParentArc<Mutex<VC>>>,
-    prod_context: Option<ParentArc<Mutex<PC>>>,
-
-    on_exe: Option<F>,
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Contract that produces a value if secondary context is valid at expiration and it has not been
+    /// voided by the first context.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct OptionContract<F, VC, PC, R>
+    where
+        VC: ContractContext,
+        PC: ContractContext,
+        F: FnOnce((VC, PC)) -> R,
+    {
+        timer: Timer,
+
+        void_context: ContextSlot<VC>,
+        prod_context: ContextSlot<PC>,
+
+        on_exe: Option<F>,
+
+        cancel: Cancel,
+    }
 }
 
 impl<F, VC, PC, R> OptionContract<F, VC, PC, R>
@@ -37,26 +40,34 @@ where
     F: FnOnce((VC, PC)) -> R,
 {
     pub fn new(expire: Duration, void_c: VC, prod_c: PC, on_exe: F) -> Self {
+        Self::with_timer(Timer::new(expire), void_c, prod_c, on_exe)
+    }
+
+    /// Build a contract expiring at `deadline`.
+    pub fn new_at(deadline: Instant, void_c: VC, prod_c: PC, on_exe: F) -> Self {
+        Self::with_timer(Timer::at(deadline), void_c, prod_c, on_exe)
+    }
+
+    /// Build a contract expiring once the wall-clock time reaches `deadline`, use it for contracts
+    /// restored from storage.
+    pub fn new_at_system_time(deadline: SystemTime, void_c: VC, prod_c: PC, on_exe: F) -> Self {
+        Self::with_timer(Timer::at(deadline), void_c, prod_c, on_exe)
+    }
+
+    /// Build a contract expiring with `timer`, use it to follow another [`Clock`](../time/trait.Clock.html).
+    pub fn with_timer(timer: Timer, void_c: VC, prod_c: PC, on_exe: F) -> Self {
         Self {
-            runner: WaitThread::new(),
-            timer: Timer::new(expire),
-            void_context: Some(ParentArc::new(Mutex::new(void_c))),
-            prod_context: Some(ParentArc::new(Mutex::new(prod_c))),
+            timer,
+            void_context: ContextSlot::new(void_c),
+            prod_context: ContextSlot::new(prod_c),
             on_exe: Some(on_exe),
+            cancel: Cancel::new(),
         }
     }
 
     fn poll_prod(&self) -> bool {
-        match &self.prod_context {
-            Some(c) => c.as_ref().lock().unwrap().poll_valid(),
-            None => false,
-        }
+        self.prod_context.is_valid()
     }
-
-    pin_utils::unsafe_pinned!(timer: Timer);
-    pin_utils::unsafe_unpinned!(void_context: Option<ParentArc<Mutex<VC>>>);
-    pin_utils::unsafe_unpinned!(prod_context: Option<ParentArc<Mutex<PC>>>);
-    pin_utils::unsafe_unpinned!(on_exe: Option<F>);
 }
 
 impl<F, VC, PC, R> Contract for OptionContract<F, VC, PC, R>
@@ -66,39 +77,57 @@ where
     F: FnOnce((VC, PC)) -> R,
 {
     fn poll_valid(&self) -> bool {
-        match &self.void_context {
-            Some(c) => c.as_ref().lock().unwrap().poll_valid(),
-            None => false,
-        }
+        self.void_context.is_valid()
     }
 
-    fn execute(mut self: std::pin::Pin<&mut Self>) -> Self::Output {
-        let vlockarc = self
-            .as_mut()
-            .void_context()
-            .take()
-            .expect("Cannot poll after expiration");
-        let plockarc = self
-            .as_mut()
-            .prod_context()
-            .take()
-            .expect("Cannot poll after expiration");
+    fn remaining(&self) -> Option<Duration> {
+        Some(self.timer.remaining())
+    }
 
-        let vcontext = vlockarc.block_into_inner().into_inner().unwrap();
-        let pcontext = plockarc.block_into_inner().into_inner().unwrap();
+    fn poll_execute(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.void_context.poll_unwrap(cx));
+        ready!(this.prod_context.poll_unwrap(cx));
 
-        let f = self
-            .as_mut()
-            .on_exe()
+        let vcontext = this.void_context.take();
+        let pcontext = this.prod_context.take();
+
+        let f = this
+            .on_exe
             .take()
             .expect("Cannot run a contract after expiration");
 
-        Status::Completed(f((vcontext, pcontext)))
+        this.cancel
+            .finish(Poll::Ready(Status::Completed(f((vcontext, pcontext)))))
+    }
+
+    // Voided by the first context, or expired without the secondary context being realised
+    fn poll_void(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.void_context.poll_unwrap(cx));
+        ready!(this.prod_context.poll_unwrap(cx));
+
+        let voided = !this.void_context.is_valid();
+        let contexts = (this.void_context.take(), this.prod_context.take());
+        this.on_exe.take();
+
+        let status = if voided {
+            Status::Voided(contexts)
+        } else {
+            Status::NotRealised(contexts)
+        };
+        this.cancel.finish(Poll::Ready(status))
     }
 
-    // This contract is bound and cannot be voided
-    fn void(self: std::pin::Pin<&mut Self>) -> Self::Output {
-        Status::Terminated
+    fn poll_cancel(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.void_context.poll_unwrap(cx));
+        ready!(this.prod_context.poll_unwrap(cx));
+
+        let contexts = (this.void_context.take(), this.prod_context.take());
+        this.on_exe.take();
+
+        this.cancel.finish(Poll::Ready(Status::Cancelled(contexts)))
     }
 }
 
@@ -108,15 +137,14 @@ where
     PC: ContractContext,
     F: FnOnce((VC, PC)) -> R,
 {
-    type Context = (LockWeak<Mutex<VC>>, LockWeak<Mutex<PC>>);
+    type Context = (ContextHandle<VC>, ContextHandle<PC>);
 
     fn get_context(&self) -> Result<Self::Context, ContextError> {
-        match (&self.void_context, &self.prod_context) {
-            (Some(ref vc), Some(ref pc)) => {
-                Ok((ParentArc::downgrade(vc), ParentArc::downgrade(pc)))
-            }
-            _ => Err(ContextError::from(ContextErrorKind::ExpiredContext)),
-        }
+        Ok((self.void_context.handle()?, self.prod_context.handle()?))
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
     }
 }
 
@@ -126,27 +154,26 @@ where
     PC: ContractContext,
     F: FnOnce((VC, PC)) -> R,
 {
-    type Output = Status<R>;
+    type Output = Status<R, (VC, PC)>;
 
     fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.runner
-            .sender()
-            .send(WaitMessage::WakeIn {
-                waker: cx.waker().clone(),
-                duration: Duration::new(0, 100),
-            })
-            .unwrap();
+        if self.cancel.poll_cancelled(cx) {
+            return self.as_mut().poll_cancel(cx);
+        }
+
+        // Updates through a ContextHandle wake the task if they invalidate the context
+        let valid = ready!(self.void_context.poll_valid(cx));
 
         let mv = (
-            self.as_mut().timer().poll(cx),
-            self.poll_valid(),
+            std::pin::Pin::new(self.as_mut().project().timer).poll(cx),
+            valid,
             self.poll_prod(),
         );
         match mv {
-            (Poll::Ready(_), true, true) => Poll::Ready(self.execute()),
-            (Poll::Ready(_), true, false) => Poll::Ready(self.void()),
+            (Poll::Ready(_), true, true) => self.as_mut().poll_execute(cx),
+            (Poll::Ready(_), true, false) => self.as_mut().poll_void(cx),
             (Poll::Pending, true, _) => Poll::Pending,
-            (_, false, _) => Poll::Ready(self.void()),
+            (_, false, _) => self.as_mut().poll_void(cx),
         }
     }
 }
@@ -158,7 +185,7 @@ where
     F: FnOnce((VC, PC)) -> R,
 {
     fn is_terminated(&self) -> bool {
-        self.void_context.is_none() || self.prod_context.is_none() || self.on_exe.is_none()
+        self.void_context.is_taken() || self.prod_context.is_taken() || self.on_exe.is_none()
     }
 }
 
@@ -166,7 +193,8 @@ where
 mod tests {
     use super::OptionContract;
     use crate::context::cmp::EqContext;
-    use crate::{ContractExt, Status};
+    use crate::time::{MockClock, Timer};
+    use crate::{Contract, ContractExt, Status};
 
     use std::time::Duration;
 
@@ -175,17 +203,19 @@ mod tests {
         let vcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
         let pcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
 
-        let c = OptionContract::new(
-            Duration::new(1, 0),
+        let clock = MockClock::new();
+        let c = OptionContract::with_timer(
+            Timer::with_clock(Duration::new(1, 0), clock.clone()),
             vcontext,
             pcontext,
             |(vcon, pcon)| -> usize { vcon.0 + pcon.0 + 1 },
         );
+        clock.advance(Duration::new(1, 0));
 
-        if let Status::Completed(val) = futures::executor::block_on(c) {
+        if let Status::Completed(val) = crate::executor::block_on(c) {
             assert_eq!(val, 5); // Contract has been executed since context is invalidated by update
         } else {
-            assert!(false);
+            unreachable!();
         }
     }
 
@@ -194,8 +224,9 @@ mod tests {
         let vcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
         let pcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
 
-        let c = OptionContract::new(
-            Duration::new(1, 0),
+        let clock = MockClock::new();
+        let c = OptionContract::with_timer(
+            Timer::with_clock(Duration::new(1, 0), clock.clone()),
             vcontext,
             pcontext,
             |(vcon, pcon)| -> usize { vcon.0 + pcon.0 + 1 },
@@ -203,17 +234,16 @@ mod tests {
 
         let handle = std::thread::spawn({
             let (vcontext, _) = c.get_context().unwrap();
-            move || match vcontext.upgrade() {
-                Some(vc) => vc.lock().unwrap().0 += 1,
-                None => {}
+            move || {
+                if let Some(mut vc) = vcontext.write() {
+                    vc.0 += 1;
+                }
+                clock.advance(Duration::new(1, 0));
             }
         });
 
-        if let Status::Completed(val) = futures::executor::block_on(c) {
-            assert_ne!(val, 6); // Contract has been voided since context is invalidated by update
-        } else {
-            assert!(true);
-        }
+        // Contract has been voided since context is invalidated by update
+        assert!(matches!(crate::executor::block_on(c), Status::Voided(_)));
 
         handle.join().unwrap();
     }
@@ -223,8 +253,9 @@ mod tests {
         let vcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
         let pcontext = EqContext(2, 2); // Context which is valid while self.0 == self.1
 
-        let c = OptionContract::new(
-            Duration::new(1, 0),
+        let clock = MockClock::new();
+        let c = OptionContract::with_timer(
+            Timer::with_clock(Duration::new(1, 0), clock.clone()),
             vcontext,
             pcontext,
             |(vcon, pcon)| -> usize { vcon.0 + pcon.0 + 1 },
@@ -232,17 +263,77 @@ mod tests {
 
         let _ = std::thread::spawn({
             let (_, pcontext) = c.get_context().unwrap();
-            move || match pcontext.upgrade() {
-                Some(pc) => pc.lock().unwrap().0 += 1,
-                None => {}
+            move || {
+                if let Some(mut pc) = pcontext.write() {
+                    pc.0 += 1;
+                }
             }
         })
         .join();
+        clock.advance(Duration::new(1, 0));
 
-        if let Status::Completed(val) = futures::executor::block_on(c) {
-            assert_ne!(val, 6); // Contract has been voided since context is invalidated by update
-        } else {
-            assert!(true);
-        }
+        // Production context has been invalidated, the contract expires without producing a value
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::NotRealised((_, EqContext(3, 2)))
+        ));
+    }
+
+    #[test]
+    fn cancelled_option_contract() {
+        let clock = MockClock::new();
+        let c = OptionContract::with_timer(
+            Timer::with_clock(Duration::new(1, 0), clock),
+            EqContext(2, 2),
+            EqContext(2, 2),
+            |(vcon, pcon)| -> usize { vcon.0 + pcon.0 + 1 },
+        );
+
+        // Both contexts are handed back
+        c.get_handle().cancel();
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Cancelled((EqContext(2, 2), EqContext(2, 2)))
+        ));
+    }
+
+    #[test]
+    fn never_expiring_option_contract() {
+        let c = OptionContract::new(
+            Duration::MAX,
+            EqContext(2, 2),
+            EqContext(2, 2),
+            |(vcon, pcon)| -> usize { vcon.0 + pcon.0 + 1 },
+        );
+        assert_eq!(c.remaining(), Some(Duration::MAX));
+
+        let (vcontext, _) = c.get_context().unwrap();
+        let handle = std::thread::spawn(move || {
+            if let Some(mut vc) = vcontext.write() {
+                vc.0 += 1;
+            }
+        });
+
+        // Only the invalidation of the context ends the contract
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Voided((EqContext(3, 2), EqContext(2, 2)))
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn system_clock_option_contract() {
+        // Driven by the timers of the enabled runtime feature
+        let c = OptionContract::new(
+            Duration::from_millis(20),
+            EqContext(2, 2),
+            EqContext(2, 2),
+            |(vcon, pcon)| -> usize { vcon.0 + pcon.0 },
+        );
+
+        let start = std::time::Instant::now();
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(4)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
     }
 }
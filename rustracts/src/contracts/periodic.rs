@@ -0,0 +1,282 @@
+use std::time::Duration;
+
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextHandle, ContextSlot, ContractContext};
+use crate::time::Timer;
+use crate::ContractHandle;
+
+use futures::{
+    future::Future,
+    ready,
+    stream::{FusedStream, Stream},
+    task::{Context, Poll},
+};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Recurring contract that produces a value from its context at every period while it is
+    /// valid, it is a stream of the produced values.
+    ///
+    /// The context is owned for the whole life of the contract so its handles stay valid from one
+    /// period to the next. The stream ends once the context is invalidated or the contract is
+    /// cancelled, the context can then be recovered with
+    /// [`into_context`](#method.into_context).
+    ///
+    /// Values are produced with exclusive access to the context, the stream is pending while
+    /// [`ContextRef`](context/struct.ContextRef.html)s read it.
+    ///
+    /// The contract is a stream rather than a future, it does not implement
+    /// [`Contract`](trait.Contract.html) and cannot be combined or managed like the other
+    /// contracts. Its context and cancellation handles are available from inherent methods.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct PeriodicContract<F, C, R>
+    where
+        C: ContractContext,
+        F: FnMut(&mut C) -> R,
+    {
+        timer: Timer,
+        period: Duration,
+
+        context: ContextSlot<C>,
+
+        on_exe: F,
+
+        cancel: Cancel,
+        done: bool,
+    }
+}
+
+impl<F, C, R> PeriodicContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnMut(&mut C) -> R,
+{
+    pub fn new(period: Duration, context: C, on_exe: F) -> Self {
+        Self::with_timer(Timer::new(period), period, context, on_exe)
+    }
+
+    /// Build a contract producing its first value when `timer` expires and then every `period`,
+    /// use it to follow another [`Clock`](../time/trait.Clock.html).
+    pub fn with_timer(timer: Timer, period: Duration, context: C, on_exe: F) -> Self {
+        Self {
+            timer,
+            period,
+            context: ContextSlot::new(context),
+            on_exe,
+            cancel: Cancel::new(),
+            done: false,
+        }
+    }
+
+    /// Get a thread-safe handle to the context, it stays valid until the stream ends.
+    pub fn get_context(&self) -> Result<ContextHandle<C>, ContextError> {
+        self.context.handle()
+    }
+
+    /// Get a thread-safe handle to cancel the contract, the stream ends once it is cancelled.
+    pub fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
+    }
+
+    /// Recover the context once the stream has ended.
+    pub fn into_context(mut self) -> Option<C> {
+        if self.done {
+            Some(self.context.take())
+        } else {
+            None
+        }
+    }
+}
+
+impl<F, C, R> Stream for PeriodicContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnMut(&mut C) -> R,
+{
+    type Item = R;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        // Updates through a ContextHandle wake the task if they invalidate the context, it is
+        // unwrapped once the readers alive at termination have dropped
+        let cancelled = this.cancel.poll_cancelled(cx);
+        if cancelled || !ready!(this.context.poll_valid(cx)) {
+            ready!(this.context.poll_unwrap(cx));
+            *this.done = true;
+            return this.cancel.finish(Poll::Ready(None));
+        }
+
+        ready!(std::pin::Pin::new(&mut *this.timer).poll(cx));
+
+        // Readers of the context wake the task once they drop, the period is settled then
+        let on_exe = this.on_exe;
+        let value = ready!(this.context.poll_update(cx, |context| on_exe(context)));
+
+        // The next period starts at the end of this one, late polls do not drift
+        let next = this.timer.deadline() + *this.period;
+        this.timer.reset(next);
+
+        Poll::Ready(Some(value))
+    }
+}
+
+impl<F, C, R> FusedStream for PeriodicContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnMut(&mut C) -> R,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PeriodicContract;
+    use crate::context::cmp::GtContext;
+    use crate::time::{MockClock, Timer};
+
+    use futures::StreamExt;
+
+    use std::time::Duration;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn periodic_settlements() {
+        let clock = MockClock::new();
+        let mut c = PeriodicContract::with_timer(
+            Timer::with_clock(HOUR, clock.clone()),
+            HOUR,
+            GtContext(3, 0), // Context is valid while self.0 > self.1
+            |con| {
+                con.0 -= 1;
+                con.0
+            },
+        );
+
+        // Every boundary passed settles once, the stream ends when the budget runs out
+        clock.advance(HOUR * 5);
+        let payouts: Vec<_> = crate::executor::block_on(c.by_ref().collect());
+        assert_eq!(payouts, vec![2, 1, 0]);
+        assert!(matches!(c.into_context(), Some(GtContext(0, 0))));
+    }
+
+    #[test]
+    fn periodic_handles_across_periods() {
+        let clock = MockClock::new();
+        let mut c = PeriodicContract::with_timer(
+            Timer::with_clock(HOUR, clock.clone()),
+            HOUR,
+            GtContext(1, 0),
+            |con| con.0,
+        );
+        let handle = c.get_context().unwrap();
+
+        crate::executor::block_on(async {
+            assert!(futures::poll!(c.next()).is_pending());
+            clock.advance(HOUR);
+            assert_eq!(c.next().await, Some(1));
+
+            // The handle taken before the first period still updates the context
+            handle.write().unwrap().0 += 1;
+            clock.advance(HOUR);
+            assert_eq!(c.next().await, Some(2));
+
+            handle.write().unwrap().0 = 0;
+            assert_eq!(c.next().await, None);
+        });
+        assert!(handle.read().is_none());
+    }
+
+    #[test]
+    fn periodic_reader_on_same_thread() {
+        let clock = MockClock::new();
+        let mut c = PeriodicContract::with_timer(
+            Timer::with_clock(HOUR, clock.clone()),
+            HOUR,
+            GtContext(1, 0),
+            |con| con.0,
+        );
+        let reader = c.get_context().unwrap().read().unwrap();
+        clock.advance(HOUR);
+
+        // The settlement waits for the reader held by the polling task without blocking it, the
+        // reader wakes it on drop
+        let (value, _) = crate::executor::block_on(futures::future::join(c.next(), async move {
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    return futures::task::Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                futures::task::Poll::Pending
+            })
+            .await;
+            drop(reader);
+        }));
+        assert_eq!(value, Some(1));
+    }
+
+    #[test]
+    fn periodic_never_settles_again() {
+        let clock = MockClock::new();
+        let mut c = PeriodicContract::with_timer(
+            Timer::with_clock(HOUR, clock.clone()),
+            Duration::MAX,
+            GtContext(1, 0),
+            |con| con.0,
+        );
+
+        // The period after the first settlement is out of range, the next one never comes
+        crate::executor::block_on(async {
+            clock.advance(HOUR);
+            assert_eq!(c.next().await, Some(1));
+            clock.advance(HOUR * 24);
+            assert!(futures::poll!(c.next()).is_pending());
+        });
+    }
+
+    #[test]
+    fn periodic_cancelled() {
+        let clock = MockClock::new();
+        let c = PeriodicContract::with_timer(
+            Timer::with_clock(HOUR, clock),
+            HOUR,
+            GtContext(1, 0),
+            |con| con.0,
+        );
+
+        let handle = c.get_handle();
+        let canceller = std::thread::spawn({
+            let handle = handle.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                handle.cancel();
+            }
+        });
+
+        assert_eq!(crate::executor::block_on(c.count()), 0);
+        assert!(handle.is_finished());
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn periodic_system_clock() {
+        // Driven by the timers of the enabled runtime feature
+        let c = PeriodicContract::new(Duration::from_millis(10), GtContext(2, 0), |con| {
+            con.0 -= 1;
+            con.0
+        });
+
+        let start = std::time::Instant::now();
+        let payouts: Vec<_> = crate::executor::block_on(c.collect());
+        assert_eq!(payouts, vec![1, 0]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use crate::cancel::Cancel;
+use crate::context::{ContextError, ContextHandle, ContextSlot, ContractContext};
+use crate::time::Timer;
+use crate::{Contract, ContractExt, ContractHandle, Status};
+
+use futures::{
+    future::{FusedFuture, Future},
+    ready,
+    task::{Context, Poll},
+};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// Contract that produces a value as soon as its context becomes valid, it expires if it has a
+    /// timer and the context is still not valid at the deadline.
+    #[must_use = "contracts do nothing unless polled or awaited"]
+    pub struct TriggerContract<F, C, R>
+    where
+        C: ContractContext,
+        F: FnOnce(C) -> R,
+    {
+        timer: Option<Timer>,
+
+        context: ContextSlot<C>,
+
+        on_exe: Option<F>,
+
+        cancel: Cancel,
+    }
+}
+
+impl<F, C, R> TriggerContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnOnce(C) -> R,
+{
+    pub fn new(context: C, on_exe: F) -> Self {
+        Self::build(None, context, on_exe)
+    }
+
+    /// Build a contract expiring with `timer` if its context has not become valid before.
+    pub fn with_timer(timer: Timer, context: C, on_exe: F) -> Self {
+        Self::build(Some(timer), context, on_exe)
+    }
+
+    fn build(timer: Option<Timer>, context: C, on_exe: F) -> Self {
+        Self {
+            timer,
+            context: ContextSlot::new(context),
+            on_exe: Some(on_exe),
+            cancel: Cancel::new(),
+        }
+    }
+}
+
+impl<F, C, R> Contract for TriggerContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnOnce(C) -> R,
+{
+    // The context becoming valid triggers the contract, only the deadline voids it
+    fn poll_valid(&self) -> bool {
+        !self.timer.as_ref().is_some_and(Timer::expired)
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.timer.as_ref().map(Timer::remaining)
+    }
+
+    fn poll_execute(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+
+        let f = this.on_exe.take().expect("Cannot poll after return");
+
+        this.cancel
+            .finish(Poll::Ready(Status::Completed(f(context))))
+    }
+
+    fn poll_void(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_exe.take();
+
+        this.cancel.finish(Poll::Ready(Status::Expired(context)))
+    }
+
+    fn poll_cancel(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let context = ready!(this.context.poll_into_inner(cx));
+        this.on_exe.take();
+
+        this.cancel.finish(Poll::Ready(Status::Cancelled(context)))
+    }
+}
+
+impl<F, C, R> ContractExt for TriggerContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnOnce(C) -> R,
+{
+    type Context = ContextHandle<C>;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        self.context.handle()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        self.cancel.handle()
+    }
+}
+
+impl<F, C, R> Future for TriggerContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnOnce(C) -> R,
+{
+    type Output = Status<R, C>;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.cancel.poll_cancelled(cx) {
+            return self.as_mut().poll_cancel(cx);
+        }
+
+        // Updates through a ContextHandle wake the task if they make the context valid, a context
+        // valid at the deadline still triggers the contract
+        let valid = ready!(self.context.poll_valid(cx));
+
+        let expired = match self.as_mut().project().timer {
+            Some(timer) => std::pin::Pin::new(timer).poll(cx).is_ready(),
+            None => false,
+        };
+        match (valid, expired) {
+            (true, _) => self.as_mut().poll_execute(cx),
+            (false, true) => self.as_mut().poll_void(cx),
+            (false, false) => Poll::Pending,
+        }
+    }
+}
+
+impl<F, C, R> FusedFuture for TriggerContract<F, C, R>
+where
+    C: ContractContext,
+    F: FnOnce(C) -> R,
+{
+    fn is_terminated(&self) -> bool {
+        self.context.is_taken() || self.on_exe.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TriggerContract;
+    use crate::context::cmp::GtContext;
+    use crate::time::{MockClock, Timer};
+    use crate::{Contract, ContractExt, Status};
+
+    use std::time::Duration;
+
+    #[test]
+    fn trigger_woken_by_update() {
+        let context = GtContext(1, 2); // Context is valid once self.0 > self.1
+
+        let c = TriggerContract::new(context, |con| -> usize { con.0 * 10 });
+
+        let handle = std::thread::spawn({
+            let mcontext = c.get_context().unwrap();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                if let Some(mut context) = mcontext.write() {
+                    context.0 = 3; // Triggers the contract while it is awaited
+                }
+            }
+        });
+
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Completed(30)
+        ));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn trigger_valid_at_start() {
+        let c = TriggerContract::new(true, |_| -> usize { 1 });
+        assert!(matches!(crate::executor::block_on(c), Status::Completed(1)));
+    }
+
+    #[test]
+    fn trigger_expired_contract() {
+        let clock = MockClock::new();
+        let c = TriggerContract::with_timer(
+            Timer::with_clock(Duration::from_secs(60), clock.clone()),
+            GtContext(1, 2),
+            |con| -> usize { con.0 },
+        );
+        assert_eq!(c.remaining(), Some(Duration::from_secs(60)));
+
+        // The context never becomes valid, it is handed back at the deadline
+        clock.advance(Duration::from_secs(60));
+        assert!(!c.poll_valid());
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Expired(GtContext(1, 2))
+        ));
+    }
+
+    #[test]
+    fn trigger_system_clock_contract() {
+        // Driven by the timers of the enabled runtime feature
+        let c = TriggerContract::with_timer(
+            Timer::new(Duration::from_millis(20)),
+            GtContext(1, 2),
+            |con| -> usize { con.0 },
+        );
+
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            crate::executor::block_on(c),
+            Status::Expired(GtContext(1, 2))
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
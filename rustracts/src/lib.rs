@@ -5,6 +5,10 @@
 //!
 //! Contracts are valid futures that can be run to completion on a reactor or awaited in an async
 //! block.
+//!
+//! Their deadlines are woken by a shared timer wheel thread, or by the timers of a runtime
+//! feature: `tokio` inside a tokio runtime, `smol` or `async-std` everywhere. When both `smol`
+//! and `async-std` are enabled the timers of `smol` are used.
 
 #![deny(clippy::all)]
 
@@ -15,11 +19,32 @@ pub trait Contract: ::futures::future::Future {
         true
     }
 
-    /// Produce a status of the contract on expiration.
-    fn execute(self: std::pin::Pin<&mut Self>) -> Self::Output;
+    /// Time left before the contract expires, `None` for contracts without a term.
+    fn remaining(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// Produce a status of the contract on expiration, pending until the readers of its context
+    /// have dropped.
+    fn poll_execute(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output>;
 
-    /// Produce a status of the contract on cancel.
-    fn void(self: std::pin::Pin<&mut Self>) -> Self::Output;
+    /// Produce a status of the contract on cancel, pending until the readers of its context have
+    /// dropped.
+    fn poll_void(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output>;
+
+    /// Produce a status of the contract once it has been cancelled through a
+    /// [`ContractHandle`](struct.ContractHandle.html), pending until the readers of its context
+    /// have dropped.
+    fn poll_cancel(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output>;
 }
 
 /// Extention trait for Contracts.
@@ -29,30 +54,274 @@ pub trait ContractExt: Contract {
 
     /// Get a thread-safe handle to a ContractContext.
     fn get_context(&self) -> Result<Self::Context, ContextError>;
+
+    /// Get a thread-safe handle to cancel the contract.
+    fn get_handle(&self) -> ContractHandle;
+
+    /// Map the value produced by this contract on completion.
+    fn map<R, C, U, F>(self, f: F) -> combinators::Map<Self, F>
+    where
+        Self: Sized + Contract<Output = Status<R, C>>,
+        F: FnOnce(R) -> U,
+    {
+        combinators::Map::new(self, f)
+    }
+
+    /// Run the contract built from the value produced by this contract on completion.
+    ///
+    /// The context returned on termination is the one of the contract that terminated.
+    fn and_then<R, C, U, D, G, B>(self, g: G) -> combinators::AndThen<Self, G, B>
+    where
+        Self: Sized + Contract<Output = Status<R, C>>,
+        B: Contract<Output = Status<U, D>>,
+        G: FnOnce(R) -> B,
+    {
+        combinators::AndThen::new(self, g)
+    }
+
+    /// Run a fallback contract built from the recovered context if this contract expires, is
+    /// voided or is not realised. A cancelled contract ends the chain without building it.
+    ///
+    /// The context returned on termination is the one of the contract that terminated.
+    fn or_else<R, C, D, H, B>(self, h: H) -> combinators::OrElse<Self, H, B>
+    where
+        Self: Sized + Contract<Output = Status<R, C>>,
+        B: Contract<Output = Status<R, D>>,
+        H: FnOnce(C) -> B,
+    {
+        combinators::OrElse::new(self, h)
+    }
+
+    /// Map the context handed back by this contract when it terminates without producing a value.
+    fn map_context<R, C, D, F>(self, f: F) -> combinators::MapContext<Self, F>
+    where
+        Self: Sized + Contract<Output = Status<R, C>>,
+        F: FnOnce(C) -> D,
+    {
+        combinators::MapContext::new(self, f)
+    }
+
+    /// Map the handles returned by [`get_context`](#tymethod.get_context), use it along with
+    /// [`map_context`](#method.map_context) to manage different contracts as trait objects.
+    fn map_handle<H, F>(self, f: F) -> combinators::MapHandle<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Context) -> H,
+    {
+        combinators::MapHandle::new(self, f)
+    }
+}
+
+impl<C> Contract for Box<C>
+where
+    C: Contract + Unpin + ?Sized,
+{
+    fn poll_valid(&self) -> bool {
+        (**self).poll_valid()
+    }
+
+    fn remaining(&self) -> Option<std::time::Duration> {
+        (**self).remaining()
+    }
+
+    fn poll_execute(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut **self.get_mut()).poll_execute(cx)
+    }
+
+    fn poll_void(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut **self.get_mut()).poll_void(cx)
+    }
+
+    fn poll_cancel(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut **self.get_mut()).poll_cancel(cx)
+    }
+}
+
+impl<C> ContractExt for Box<C>
+where
+    C: ContractExt + Unpin + ?Sized,
+{
+    type Context = C::Context;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        (**self).get_context()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        (**self).get_handle()
+    }
+}
+
+/// Boxed contracts can be managed as trait objects, e.g.
+/// `Pin<Box<dyn ContractExt<Output = Status<R, C>, Context = H>>>`.
+impl<C> Contract for std::pin::Pin<Box<C>>
+where
+    C: Contract + ?Sized,
+{
+    fn poll_valid(&self) -> bool {
+        (**self).poll_valid()
+    }
+
+    fn remaining(&self) -> Option<std::time::Duration> {
+        (**self).remaining()
+    }
+
+    fn poll_execute(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        self.get_mut().as_mut().poll_execute(cx)
+    }
+
+    fn poll_void(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        self.get_mut().as_mut().poll_void(cx)
+    }
+
+    fn poll_cancel(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut ::futures::task::Context,
+    ) -> ::futures::task::Poll<Self::Output> {
+        self.get_mut().as_mut().poll_cancel(cx)
+    }
+}
+
+impl<C> ContractExt for std::pin::Pin<Box<C>>
+where
+    C: ContractExt + ?Sized,
+{
+    type Context = C::Context;
+
+    fn get_context(&self) -> Result<Self::Context, ContextError> {
+        (**self).get_context()
+    }
+
+    fn get_handle(&self) -> ContractHandle {
+        (**self).get_handle()
+    }
 }
 
 /// Status on completion/invalidation of a contract.
-pub enum Status<R> {
+///
+/// Contracts that terminate without producing a value hand back their context so the resources it
+/// holds are not lost.
+pub enum Status<R, C = ()> {
     /// Contract has successfully produced a value.
     Completed(R),
 
-    /// Contract has ended and did not produce a value.
-    Terminated,
+    /// Contract has been voided by its context before expiration.
+    Voided(C),
+
+    /// Contract has reached its term without being executed.
+    Expired(C),
+
+    /// Contract has expired but the conditions to produce a value were not met.
+    NotRealised(C),
+
+    /// Contract has been cancelled before it terminated.
+    Cancelled(C),
 }
 
+impl<R, C> Status<R, C> {
+    /// Map the value of a completed contract.
+    pub fn map<U, F>(self, f: F) -> Status<U, C>
+    where
+        F: FnOnce(R) -> U,
+    {
+        match self {
+            Status::Completed(value) => Status::Completed(f(value)),
+            Status::Voided(context) => Status::Voided(context),
+            Status::Expired(context) => Status::Expired(context),
+            Status::NotRealised(context) => Status::NotRealised(context),
+            Status::Cancelled(context) => Status::Cancelled(context),
+        }
+    }
+
+    /// Map the context recovered from a terminated contract.
+    pub fn map_context<D, F>(self, f: F) -> Status<R, D>
+    where
+        F: FnOnce(C) -> D,
+    {
+        match self {
+            Status::Completed(value) => Status::Completed(value),
+            Status::Voided(context) => Status::Voided(f(context)),
+            Status::Expired(context) => Status::Expired(f(context)),
+            Status::NotRealised(context) => Status::NotRealised(f(context)),
+            Status::Cancelled(context) => Status::Cancelled(f(context)),
+        }
+    }
+
+    /// Check wether the contract has produced a value.
+    pub fn is_completed(&self) -> bool {
+        matches!(self, Status::Completed(_))
+    }
+
+    /// Get the context back from a contract that did not produce a value.
+    pub fn into_context(self) -> Option<C> {
+        match self {
+            Status::Completed(_) => None,
+            Status::Voided(context)
+            | Status::Expired(context)
+            | Status::NotRealised(context)
+            | Status::Cancelled(context) => Some(context),
+        }
+    }
+}
+
+mod cancel;
 mod contracts;
 
+// Contract tests run on the executor of the enabled runtime feature.
+#[cfg(test)]
+mod executor {
+    #[cfg(all(feature = "async-std", not(feature = "smol")))]
+    pub(crate) use async_std::task::block_on;
+    #[cfg(not(any(feature = "tokio", feature = "async-std", feature = "smol")))]
+    pub(crate) use futures::executor::block_on;
+    #[cfg(feature = "smol")]
+    pub(crate) use smol::block_on;
+
+    #[cfg(all(feature = "tokio", not(any(feature = "async-std", feature = "smol"))))]
+    pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+}
+
+/// Contracts returned by the [`ContractExt`](trait.ContractExt.html) combinators.
+pub mod combinators;
+
 /// Time utilities.
 pub mod time;
 
 /// ContractContext implementations.
 pub mod context;
 
-/// Parkable waker threads.
+/// Timer driver that wakes contracts at their deadline.
 pub mod park;
 
+/// Supervisor of many live contracts.
+pub mod manager;
+
+/// Thread-safe handle to cancel a running contract.
+pub use crate::cancel::ContractHandle;
+
 /// Trait that defines a valid context for a contract.
-pub use context::{ContextError, ContractContext};
+pub use context::{ContextError, ContractContext, ContractContextExt};
 
 /// Duration based contract produces a value at a point in the future using the available context if it
 /// has not been voided before.
@@ -64,3 +333,10 @@ pub use crate::contracts::OnKillContract;
 /// Duration based contract produces a value at a point in the future if it has not been voided and
 /// secondary context has been realized.
 pub use crate::contracts::OptionContract;
+
+/// Recurring contract produces a value at every period while its context is valid.
+pub use crate::contracts::PeriodicContract;
+
+/// Contract that produces a value as soon as its context becomes valid, optionally before a
+/// deadline.
+pub use crate::contracts::TriggerContract;
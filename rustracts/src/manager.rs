@@ -0,0 +1,447 @@
+//! Contracts spawned on a [`ContractManager`](struct.ContractManager.html) are tracked by id until
+//! they terminate. The manager lists the live contracts, hands out their context and cancellation
+//! handles and yields their outcomes as a [`Stream`](../../futures/stream/trait.Stream.html).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::{ContractExt, ContractHandle};
+
+use futures::{
+    future::Future,
+    ready,
+    stream::{FusedStream, FuturesUnordered, Stream},
+    task::{Context, Poll},
+};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    // Contract polled by the manager along with its id
+    struct Managed<K, T> {
+        id: Option<K>,
+        handle: ContractHandle,
+        #[pin]
+        contract: T,
+    }
+}
+
+impl<K, T> Managed<K, T> {
+    fn id(&self) -> &K {
+        self.id
+            .as_ref()
+            .expect("Terminated contracts are not managed")
+    }
+}
+
+impl<K, T> Future for Managed<K, T>
+where
+    T: Future,
+{
+    type Output = (K, T::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let status = ready!(this.contract.poll(cx));
+        let id = this.id.take().expect("Cannot poll after completion");
+        Poll::Ready((id, status))
+    }
+}
+
+/// Supervisor of many live contracts identified by an id of type `K`.
+///
+/// The manager is a [`Stream`](../../futures/stream/trait.Stream.html) of the id and status of the
+/// contracts as they terminate, it polls them all at once. The stream ends while no contract is
+/// live, it can be polled again once more contracts are spawned.
+///
+/// Ids are unique among the live contracts, they are looked up without going through the other
+/// contracts. An id can be spawned again once its contract has terminated.
+///
+/// # Examples
+/// ```rust
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use rustracts::{manager::ContractManager, FuturesContract, Status};
+///
+/// fn settle(context: usize) -> usize {
+///     context * 2
+/// }
+///
+/// let mut manager = ContractManager::new();
+/// manager.spawn("slow", FuturesContract::new(Duration::from_secs(3600), 1, settle)).unwrap();
+/// manager.spawn("fast", FuturesContract::new(Duration::from_millis(10), 2, settle)).unwrap();
+/// assert!(manager.spawn("fast", FuturesContract::new(Duration::ZERO, 3, settle)).is_err());
+///
+/// manager.cancel(&"slow");
+/// let mut outcomes: Vec<_> = futures::executor::block_on(manager.collect());
+/// outcomes.sort_by_key(|(id, _)| *id);
+/// assert!(matches!(outcomes[0], ("fast", Status::Completed(4))));
+/// assert!(matches!(outcomes[1], ("slow", Status::Cancelled(1))));
+/// ```
+pub struct ContractManager<K, T>
+where
+    T: ContractExt,
+{
+    contracts: FuturesUnordered<Managed<K, T>>,
+    tracked: HashMap<K, Tracked<T::Context>>,
+}
+
+// Handles of a live contract, reachable by id without going through the contracts
+struct Tracked<C> {
+    handle: ContractHandle,
+    context: Option<C>,
+}
+
+impl<K, T> ContractManager<K, T>
+where
+    T: ContractExt,
+{
+    /// Build a manager without contracts.
+    pub fn new() -> Self {
+        Self {
+            contracts: FuturesUnordered::new(),
+            tracked: HashMap::new(),
+        }
+    }
+
+    /// Number of live contracts.
+    pub fn len(&self) -> usize {
+        self.contracts.len()
+    }
+
+    /// Check wether no contract is live.
+    pub fn is_empty(&self) -> bool {
+        self.contracts.is_empty()
+    }
+
+    /// Ids of the live contracts with the time left before they expire, `None` for the contracts
+    /// without a term.
+    pub fn live(&self) -> impl Iterator<Item = (&K, Option<Duration>)> {
+        self.iter()
+            .map(|managed| (managed.id(), managed.contract.remaining()))
+    }
+
+    /// Cancel the live contracts matching `predicate`, returns how many were cancelled.
+    pub fn cancel_where<P>(&self, mut predicate: P) -> usize
+    where
+        P: FnMut(&K, &T) -> bool,
+    {
+        self.iter()
+            .filter(|managed| predicate(managed.id(), &managed.contract))
+            .map(|managed| managed.handle.cancel())
+            .count()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Managed<K, T>> {
+        Pin::new(&self.contracts).iter_pin_ref().map(Pin::get_ref)
+    }
+}
+
+impl<K, T> ContractManager<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: ContractExt,
+{
+    /// Start tracking `contract` under `id`, it is polled along with the stream of the manager.
+    ///
+    /// Fails and hands the contract back if a live contract already has this id.
+    pub fn spawn(&mut self, id: K, contract: T) -> Result<ContractHandle, SpawnError<K, T>> {
+        if self.tracked.contains_key(&id) {
+            return Err(SpawnError { id, contract });
+        }
+
+        let handle = contract.get_handle();
+        let tracked = Tracked {
+            handle: handle.clone(),
+            context: contract.get_context().ok(),
+        };
+        self.tracked.insert(id.clone(), tracked);
+        self.contracts.push(Managed {
+            id: Some(id),
+            handle: handle.clone(),
+            contract,
+        });
+        Ok(handle)
+    }
+
+    /// Get a handle to the context of the live contract `id`, `None` if there is no such contract
+    /// or if its context had expired when it was spawned.
+    pub fn get_context(&self, id: &K) -> Option<T::Context>
+    where
+        T::Context: Clone,
+    {
+        self.tracked
+            .get(id)
+            .and_then(|tracked| tracked.context.clone())
+    }
+
+    /// Get a handle to cancel the live contract `id`.
+    pub fn get_handle(&self, id: &K) -> Option<ContractHandle> {
+        self.tracked.get(id).map(|tracked| tracked.handle.clone())
+    }
+
+    /// Cancel the live contract `id`, returns false if there is no such contract.
+    pub fn cancel(&self, id: &K) -> bool {
+        self.tracked
+            .get(id)
+            .map(|tracked| tracked.handle.cancel())
+            .is_some()
+    }
+}
+
+// The contracts are pinned by the FuturesUnordered, the manager never pins the other fields
+impl<K, T> Unpin for ContractManager<K, T> where T: ContractExt {}
+
+impl<K, T> Default for ContractManager<K, T>
+where
+    T: ContractExt,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, T> fmt::Debug for ContractManager<K, T>
+where
+    K: fmt::Debug,
+    T: ContractExt,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(Managed::id))
+            .finish()
+    }
+}
+
+impl<K, T> Stream for ContractManager<K, T>
+where
+    K: Hash + Eq,
+    T: ContractExt,
+{
+    type Item = (K, T::Output);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let outcome = ready!(Pin::new(&mut self.contracts).poll_next(cx));
+        if let Some((id, _)) = &outcome {
+            self.tracked.remove(id);
+        }
+        Poll::Ready(outcome)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<K, T> FusedStream for ContractManager<K, T>
+where
+    K: Hash + Eq,
+    T: ContractExt,
+{
+    fn is_terminated(&self) -> bool {
+        self.contracts.is_terminated()
+    }
+}
+
+/// Error returned by [`spawn`](struct.ContractManager.html#method.spawn) when a live contract
+/// already has the id, the contract is handed back.
+pub struct SpawnError<K, T> {
+    id: K,
+    contract: T,
+}
+
+impl<K, T> SpawnError<K, T> {
+    /// Id of the live contract.
+    pub fn id(&self) -> &K {
+        &self.id
+    }
+
+    /// Recover the contract that was not spawned.
+    pub fn into_contract(self) -> T {
+        self.contract
+    }
+}
+
+impl<K, T> fmt::Debug for SpawnError<K, T>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpawnError").field("id", &self.id).finish()
+    }
+}
+
+impl<K, T> fmt::Display for SpawnError<K, T>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a live contract already has the id {:?}", self.id)
+    }
+}
+
+impl<K, T> Error for SpawnError<K, T> where K: fmt::Debug {}
+
+#[cfg(test)]
+mod tests {
+    use super::ContractManager;
+    use crate::context::cmp::GtContext;
+    use crate::context::ContextHandle;
+    use crate::time::{MockClock, Timer};
+    use crate::{ContractExt, FuturesContract, OptionContract, Status};
+
+    use futures::StreamExt;
+
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    type Contract =
+        FuturesContract<fn(GtContext<usize, usize>) -> usize, GtContext<usize, usize>, usize>;
+
+    fn contract(clock: &MockClock, secs: u64, value: usize) -> Contract {
+        FuturesContract::with_timer(
+            Timer::with_clock(Duration::from_secs(secs), clock.clone()),
+            GtContext(value, 0),
+            |con| con.0,
+        )
+    }
+
+    #[test]
+    fn manager_outcomes() {
+        let clock = MockClock::new();
+        let mut manager = ContractManager::new();
+        for id in 1..=4 {
+            manager.spawn(id, contract(&clock, id as u64, id)).unwrap();
+        }
+
+        let mut live: Vec<_> = manager.live().map(|(id, left)| (*id, left)).collect();
+        live.sort();
+        assert_eq!(live[0], (1, Some(Duration::from_secs(1))));
+        assert_eq!(live.len(), 4);
+
+        // Void the second contract through its context, cancel the third one
+        if let Some(mut context) = manager.get_context(&2).unwrap().write() {
+            context.0 = 0;
+        }
+        assert!(manager.cancel(&3));
+        assert!(!manager.cancel(&5));
+        clock.advance(Duration::from_secs(4));
+
+        let mut outcomes = crate::executor::block_on(manager.by_ref().collect::<Vec<_>>());
+        outcomes.sort_by_key(|(id, _)| *id);
+        assert!(matches!(outcomes[0], (1, Status::Completed(1))));
+        assert!(matches!(outcomes[1], (2, Status::Voided(GtContext(0, 0)))));
+        assert!(matches!(
+            outcomes[2],
+            (3, Status::Cancelled(GtContext(3, 0)))
+        ));
+        assert!(matches!(outcomes[3], (4, Status::Completed(4))));
+        assert!(manager.is_empty() && manager.get_context(&1).is_none());
+    }
+
+    #[test]
+    fn manager_cancel_where() {
+        let clock = MockClock::new();
+        let mut manager = ContractManager::new();
+        let handles: Vec<_> = (1..=10)
+            .map(|id| manager.spawn(id, contract(&clock, 60, id)).unwrap())
+            .collect();
+        clock.advance(Duration::from_secs(30));
+
+        // Cancel the contracts which context is odd, the others run to their term
+        let cancelled =
+            manager.cancel_where(|_, c| c.get_context().unwrap().read().unwrap().0 % 2 == 1);
+        assert_eq!(cancelled, 5);
+        assert!(manager
+            .live()
+            .all(|(_, left)| left == Some(Duration::from_secs(30))));
+        assert_eq!(manager.get_handle(&2), Some(handles[1].clone()));
+
+        let ticker = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            clock.advance(Duration::from_secs(30));
+        });
+        let outcomes = crate::executor::block_on(manager.collect::<Vec<_>>());
+        ticker.join().unwrap();
+
+        for (id, status) in outcomes {
+            assert_eq!(status.is_completed(), id % 2 == 0);
+            assert!(handles[id - 1].is_finished());
+        }
+    }
+
+    #[test]
+    fn manager_boxed_contracts() {
+        type Boxed = Pin<
+            Box<
+                dyn ContractExt<
+                    Output = Status<usize, GtContext<usize, usize>>,
+                    Context = ContextHandle<GtContext<usize, usize>>,
+                >,
+            >,
+        >;
+
+        let clock = MockClock::new();
+        let mut manager = ContractManager::<&str, Boxed>::new();
+        manager
+            .spawn("futures", Box::pin(contract(&clock, 60, 1)))
+            .unwrap();
+
+        // The option contract is brought to the same status and handle types as the other one
+        let option = OptionContract::with_timer(
+            Timer::with_clock(Duration::from_secs(30), clock.clone()),
+            GtContext(2, 0),
+            false,
+            |(con, _)| con.0,
+        )
+        .map_context(|(con, _)| con)
+        .map_handle(|(con, _)| con);
+        manager.spawn("option", Box::pin(option)).unwrap();
+
+        let mut live: Vec<_> = manager.live().collect();
+        live.sort();
+        assert_eq!(live[0], (&"futures", Some(Duration::from_secs(60))));
+        assert_eq!(live[1], (&"option", Some(Duration::from_secs(30))));
+
+        manager.get_context(&"option").unwrap().write().unwrap().0 = 3;
+        clock.advance(Duration::from_secs(60));
+
+        let mut outcomes = crate::executor::block_on(manager.collect::<Vec<_>>());
+        outcomes.sort_by_key(|(id, _)| *id);
+        assert!(matches!(outcomes[0], ("futures", Status::Completed(1))));
+        assert!(matches!(
+            outcomes[1],
+            ("option", Status::NotRealised(GtContext(3, 0)))
+        ));
+    }
+
+    #[test]
+    fn manager_unique_ids() {
+        let clock = MockClock::new();
+        let mut manager = ContractManager::new();
+        let handle = manager.spawn("a", contract(&clock, 60, 1)).unwrap();
+
+        // The live contract keeps its id, the other one is handed back
+        let err = manager.spawn("a", contract(&clock, 30, 2)).unwrap_err();
+        assert_eq!(*err.id(), "a");
+        assert_eq!(
+            err.into_contract().get_context().unwrap().read().unwrap().0,
+            2
+        );
+        assert_eq!(manager.len(), 1);
+        assert_eq!(manager.get_handle(&"a"), Some(handle));
+
+        // The id is free again once its contract has terminated
+        assert!(manager.cancel(&"a"));
+        let outcome = crate::executor::block_on(manager.next());
+        assert!(matches!(outcome, Some(("a", Status::Cancelled(_)))));
+        assert!(manager.get_handle(&"a").is_none() && !manager.cancel(&"a"));
+
+        let handle = manager.spawn("a", contract(&clock, 30, 2)).unwrap();
+        assert_eq!(manager.get_handle(&"a"), Some(handle));
+        assert_eq!(manager.get_context(&"a").unwrap().read().unwrap().0, 2);
+    }
+}
@@ -1,63 +1,599 @@
-use std::sync::mpsc::{channel, Sender};
-use std::thread;
-use std::thread::JoinHandle;
-use std::time::Duration;
-
-/// Wait thread can either be scheduled a park or be ended.
-pub enum WaitMessage {
-    WakeIn {
-        waker: futures::task::Waker,
-        duration: Duration,
-    },
-    End,
+//! Hierarchical timer wheel used to wake contracts at their deadline.
+//!
+//! A single [`Driver`](struct.Driver.html) thread owns the wheel, [`Sleep`](struct.Sleep.html)
+//! futures register their deadline once and are woken when the driver reaches it. The
+//! process-wide driver is started on first use by [`Handle::global`](struct.Handle.html#method.global)
+//! but drivers can also be constructed explicitly.
+//!
+//! With the `tokio` feature timers polled inside a tokio runtime are woken by its time driver
+//! instead, no driver thread is started for them. The `smol` and `async-std` features do the same
+//! for every timer using their reactor, the reactor of `smol` is used if both are enabled.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use futures::{
+    future::{FusedFuture, Future},
+    task::{AtomicWaker, Context, Poll},
+};
+
+/// Resolution of the timer wheel, deadlines are rounded up to the next tick.
+pub const TICK: Duration = Duration::from_millis(1);
+
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+// Furthest tick a level can hold, further deadlines are cascaded down from the last level.
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS);
+
+// Registration shared between the wheel and its Sleep.
+struct Entry {
+    when: u64,
+    waker: AtomicWaker,
+    fired: AtomicBool,
+    // Level and slot holding the entry, only updated under the lock of the wheel
+    position: AtomicUsize,
+}
+
+impl Entry {
+    fn new(when: u64) -> Self {
+        Self {
+            when,
+            waker: AtomicWaker::new(),
+            fired: AtomicBool::new(false),
+            position: AtomicUsize::new(0),
+        }
+    }
+
+    fn fire(&self) {
+        self.fired.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+struct Level {
+    level: usize,
+    occupied: u64,
+    slots: Vec<Vec<Arc<Entry>>>,
+}
+
+impl Level {
+    fn new(level: usize) -> Self {
+        Self {
+            level,
+            occupied: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn slot_range(&self) -> u64 {
+        1 << (SLOT_BITS * self.level)
+    }
+
+    fn level_range(&self) -> u64 {
+        self.slot_range() << SLOT_BITS
+    }
+
+    fn slot_for(&self, when: u64) -> usize {
+        ((when >> (SLOT_BITS * self.level)) as usize) & (SLOTS - 1)
+    }
+
+    fn push(&mut self, entry: Arc<Entry>) {
+        let slot = self.slot_for(entry.when);
+        entry
+            .position
+            .store(self.level * SLOTS + slot, Ordering::Relaxed);
+        self.slots[slot].push(entry);
+        self.occupied |= 1 << slot;
+    }
+
+    fn remove(&mut self, slot: usize, entry: &Arc<Entry>) {
+        let entries = &mut self.slots[slot];
+        if let Some(index) = entries.iter().position(|e| Arc::ptr_eq(e, entry)) {
+            entries.swap_remove(index);
+            if entries.is_empty() {
+                self.occupied &= !(1 << slot);
+            }
+        }
+    }
+
+    fn take(&mut self, slot: usize) -> Vec<Arc<Entry>> {
+        self.occupied &= !(1 << slot);
+        std::mem::take(&mut self.slots[slot])
+    }
+
+    // First occupied slot starting from the current one and its starting tick
+    fn next_expiration(&self, elapsed: u64) -> Option<(usize, u64)> {
+        if self.occupied == 0 {
+            return None;
+        }
+        let now_slot = (elapsed / self.slot_range()) as usize % SLOTS;
+        let zeros = self.occupied.rotate_right(now_slot as u32).trailing_zeros() as usize;
+        let slot = (zeros + now_slot) % SLOTS;
+
+        let level_start = elapsed & !(self.level_range() - 1);
+        let mut deadline = level_start + slot as u64 * self.slot_range();
+        if deadline <= elapsed {
+            // Only the last level can wrap around when deadlines are further than MAX_TICKS
+            deadline += self.level_range();
+        }
+        Some((slot, deadline))
+    }
 }
 
-/// Thread that will wake an underlying Waker after parking for a duration. Or can be killed to
-/// never wake a Waker again.
-pub struct WaitThread {
-    sender: Sender<WaitMessage>,
-    handle: Option<JoinHandle<()>>,
+struct Wheel {
+    elapsed: u64,
+    levels: Vec<Level>,
 }
 
-impl WaitThread {
-    /// Create a new WaitThread
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            elapsed: 0,
+            levels: (0..LEVELS).map(Level::new).collect(),
+        }
+    }
+
+    // Entries that are already due are handed back to be fired right away.
+    fn insert(&mut self, entry: Arc<Entry>) -> Result<(), Arc<Entry>> {
+        if entry.when <= self.elapsed {
+            return Err(entry);
+        }
+        let masked = ((self.elapsed ^ entry.when) | (SLOTS as u64 - 1)).min(MAX_TICKS - 1);
+        let significant = 63 - masked.leading_zeros() as usize;
+        self.levels[significant / SLOT_BITS].push(entry);
+        Ok(())
+    }
+
+    // Unlink an entry that has not fired, it may have been taken out to be fired already.
+    fn remove(&mut self, entry: &Arc<Entry>) {
+        let position = entry.position.load(Ordering::Relaxed);
+        self.levels[position / SLOTS].remove(position % SLOTS, entry);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.levels
+            .iter()
+            .flat_map(|level| level.slots.iter())
+            .map(Vec::len)
+            .sum()
+    }
+
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        self.levels.iter().find_map(|level| {
+            level
+                .next_expiration(self.elapsed)
+                .map(|(slot, deadline)| (level.level, slot, deadline))
+        })
+    }
+
+    // Advance the wheel up to `now`, collecting the entries that have to be fired.
+    fn advance(&mut self, now: u64, fired: &mut Vec<Arc<Entry>>) {
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now {
+                break;
+            }
+            self.elapsed = deadline;
+            for entry in self.levels[level].take(slot) {
+                if let Err(entry) = self.insert(entry) {
+                    fired.push(entry);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+    }
+}
+
+struct State {
+    wheel: Wheel,
+    // Tick at which the driver thread will wake up next
+    next_wake: Option<u64>,
+    shutdown: bool,
+}
+
+struct Inner {
+    start: Instant,
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn tick_floor(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.start).as_nanos();
+        (nanos / TICK.as_nanos()) as u64
+    }
+
+    fn tick_ceil(&self, instant: Instant) -> u64 {
+        let nanos = instant.saturating_duration_since(self.start).as_nanos();
+        nanos.div_ceil(TICK.as_nanos()) as u64
+    }
+
+    fn instant_of(&self, tick: u64) -> Instant {
+        self.start + Duration::from_nanos((TICK.as_nanos() as u64).saturating_mul(tick))
+    }
+
+    fn run(&self) {
+        let mut fired = Vec::new();
+        let mut state = self.lock();
+        while !state.shutdown {
+            let now = Instant::now();
+            state.wheel.advance(self.tick_floor(now), &mut fired);
+            state.next_wake = state.wheel.next_expiration().map(|(_, _, tick)| tick);
+
+            if !fired.is_empty() {
+                drop(state);
+                fired.drain(..).for_each(|entry| entry.fire());
+                state = self.lock();
+                continue;
+            }
+
+            state = match state.next_wake {
+                Some(tick) => {
+                    let timeout = self.instant_of(tick).saturating_duration_since(now);
+                    self.condvar
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self.condvar.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+}
+
+/// Thread driving a timer wheel, pending [`Sleep`](struct.Sleep.html)s are woken when their
+/// deadline is reached.
+///
+/// Dropping the driver stops its thread, sleeps that have not fired by then are never woken.
+pub struct Driver {
+    handle: Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Driver {
+    /// Start a new driver thread.
     pub fn new() -> Self {
-        let (sender, receiver) = channel();
+        let inner = Arc::new(Inner {
+            start: Instant::now(),
+            state: Mutex::new(State {
+                wheel: Wheel::new(),
+                next_wake: None,
+                shutdown: false,
+            }),
+            condvar: Condvar::new(),
+        });
+        let thread = thread::Builder::new()
+            .name("rustracts-timer".into())
+            .spawn({
+                let inner = Arc::clone(&inner);
+                move || inner.run()
+            })
+            .expect("failed to spawn the timer driver thread");
+
         Self {
-            sender,
-            handle: Some(thread::spawn(move || loop {
-                match receiver.recv() {
-                    Ok(WaitMessage::End) => {
-                        break;
-                    }
-                    Ok(WaitMessage::WakeIn { waker, duration }) => {
-                        thread::sleep(duration);
-                        waker.wake()
-                    }
-                    Err(e) => eprintln!("Error on WaitProcess channel: {}", e),
-                };
-            })),
+            handle: Handle { inner },
+            thread: Some(thread),
         }
     }
-    /// Create a copy of the underlying sender
-    pub fn sender(&self) -> Sender<WaitMessage> {
-        self.sender.clone()
+
+    /// Get a handle to register sleeps on this driver.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
     }
 }
 
-impl Default for WaitThread {
+impl Default for Driver {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Drop for WaitThread {
+impl Drop for Driver {
+    fn drop(&mut self) {
+        self.handle.inner.lock().shutdown = true;
+        self.handle.inner.condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Cloneable handle to a [`Driver`](struct.Driver.html).
+#[derive(Clone)]
+pub struct Handle {
+    inner: Arc<Inner>,
+}
+
+impl Handle {
+    /// Handle to the process-wide driver, which is started on the first call.
+    pub fn global() -> Self {
+        static GLOBAL: OnceLock<Driver> = OnceLock::new();
+        GLOBAL.get_or_init(Driver::new).handle()
+    }
+
+    /// Register a sleep that will be woken once `deadline` is reached.
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        let entry = Arc::new(Entry::new(self.inner.tick_ceil(deadline)));
+
+        let mut state = self.inner.lock();
+        match state.wheel.insert(Arc::clone(&entry)) {
+            Ok(()) => {
+                // Only bother the driver if it would sleep past this deadline
+                if state.next_wake.is_none_or(|tick| entry.when < tick) {
+                    state.next_wake = Some(entry.when);
+                    self.inner.condvar.notify_one();
+                }
+            }
+            Err(entry) => entry.fired.store(true, Ordering::Release),
+        }
+
+        Sleep {
+            entry,
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// Future registered once on a timer wheel that completes at its deadline.
+#[must_use = "futures do nothing unless polled or awaited"]
+pub struct Sleep {
+    entry: Arc<Entry>,
+    inner: Arc<Inner>,
+}
+
+impl Sleep {
+    /// Check wether the driver has reached the deadline.
+    pub fn is_elapsed(&self) -> bool {
+        self.entry.fired.load(Ordering::Acquire)
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.is_elapsed() {
+            return Poll::Ready(());
+        }
+        self.entry.waker.register(cx.waker());
+        // Check again in case the driver fired before the waker was registered
+        if self.is_elapsed() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl FusedFuture for Sleep {
+    fn is_terminated(&self) -> bool {
+        self.is_elapsed()
+    }
+}
+
+impl Drop for Sleep {
     fn drop(&mut self) {
-        self.sender.send(WaitMessage::End).unwrap();
-        self.handle
-            .take()
-            .expect("WaitProcess cannot be killed twice")
-            .join()
-            .unwrap();
+        // Unlink the entry so sleeps dropped long before their deadline do not pile up
+        if !self.is_elapsed() {
+            self.inner.lock().wheel.remove(&self.entry);
+        }
+    }
+}
+
+// Wake-up source of the system clock, picked from the runtime it is polled on.
+pub(crate) enum Delay {
+    #[cfg(not(any(feature = "smol", feature = "async-std")))]
+    Wheel(Sleep),
+    #[cfg(feature = "tokio")]
+    Tokio(Pin<Box<tokio::time::Sleep>>),
+    #[cfg(feature = "smol")]
+    Smol(smol::Timer),
+    #[cfg(all(feature = "async-std", not(feature = "smol")))]
+    AsyncStd {
+        deadline: Instant,
+        sleep: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    },
+}
+
+impl Delay {
+    /// Delay on the runtime of the current task or on the global driver. A tokio runtime comes
+    /// first, then `smol` which takes precedence over `async-std` when both are enabled.
+    pub(crate) fn until(deadline: Instant) -> Self {
+        #[cfg(feature = "tokio")]
+        {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                return Delay::Tokio(Box::pin(tokio::time::sleep_until(deadline.into())));
+            }
+        }
+        #[cfg(feature = "smol")]
+        {
+            Delay::Smol(smol::Timer::at(deadline))
+        }
+        #[cfg(all(feature = "async-std", not(feature = "smol")))]
+        {
+            Delay::AsyncStd {
+                deadline,
+                sleep: None,
+            }
+        }
+        #[cfg(not(any(feature = "smol", feature = "async-std")))]
+        {
+            Delay::Wheel(Handle::global().sleep_until(deadline))
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.get_mut() {
+            #[cfg(not(any(feature = "smol", feature = "async-std")))]
+            Delay::Wheel(sleep) => Pin::new(sleep).poll(cx),
+            #[cfg(feature = "tokio")]
+            Delay::Tokio(sleep) => sleep.as_mut().poll(cx),
+            #[cfg(feature = "smol")]
+            Delay::Smol(timer) => Pin::new(timer).poll(cx).map(|_| ()),
+            // async-std sleeps for a duration from their first poll, it is computed then
+            #[cfg(all(feature = "async-std", not(feature = "smol")))]
+            Delay::AsyncStd { deadline, sleep } => sleep
+                .get_or_insert_with(|| {
+                    let duration = deadline.saturating_duration_since(Instant::now());
+                    Box::pin(async_std::task::sleep(duration))
+                })
+                .as_mut()
+                .poll(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Driver, Entry, Wheel, TICK};
+    use crate::{FuturesContract, Status};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn wheel_cascades_far_deadlines() {
+        let mut wheel = Wheel::new();
+        let mut fired = Vec::new();
+
+        let deadlines = [1, 63, 64, 65, 4095, 4096, 300_000];
+        for when in deadlines.iter() {
+            assert!(wheel.insert(Arc::new(Entry::new(*when))).is_ok());
+        }
+
+        for when in deadlines.iter() {
+            wheel.advance(*when - 1, &mut fired);
+            assert!(fired.is_empty());
+            wheel.advance(*when, &mut fired);
+            assert_eq!(fired.len(), 1);
+            assert_eq!(fired.pop().unwrap().when, *when);
+        }
+        assert!(wheel.next_expiration().is_none());
+    }
+
+    #[test]
+    fn dropped_sleeps_leave_the_wheel() {
+        let driver = Driver::new();
+        let handle = driver.handle();
+        let deadline = Instant::now() + Duration::from_secs(3600);
+
+        let sleeps: Vec<_> = (0..1000u64)
+            .map(|ms| handle.sleep_until(deadline + Duration::from_millis(ms)))
+            .collect();
+        assert_eq!(handle.inner.lock().wheel.len(), 1000);
+
+        drop(sleeps);
+        let state = handle.inner.lock();
+        assert_eq!(state.wheel.len(), 0);
+        assert!(state.wheel.next_expiration().is_none());
+    }
+
+    #[test]
+    fn sleep_wakes_at_deadline() {
+        let driver = Driver::new();
+        let deadline = Instant::now() + TICK * 20;
+
+        futures::executor::block_on(driver.handle().sleep_until(deadline));
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn many_sleeps_one_driver() {
+        let driver = Driver::new();
+        let start = Instant::now();
+
+        let sleeps = (1..200u64).map(|ms| {
+            driver
+                .handle()
+                .sleep_until(start + Duration::from_millis(ms % 50))
+        });
+        futures::executor::block_on(futures::future::join_all(sleeps));
+        assert!(Instant::now() >= start + Duration::from_millis(49));
+    }
+
+    // Most contract tests run on a MockClock, these go through the Delay of each backend
+
+    #[cfg(not(any(feature = "smol", feature = "async-std")))]
+    #[test]
+    fn wheel_delay_wakes_contract() {
+        assert!(matches!(
+            super::Delay::until(Instant::now()),
+            super::Delay::Wheel(_)
+        ));
+
+        let start = Instant::now();
+        let c = FuturesContract::new(Duration::from_millis(30), (), |_| -> usize { 5 });
+        assert!(matches!(
+            futures::executor::block_on(c),
+            Status::Completed(5)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn tokio_delay_wakes_contract() {
+        assert!(matches!(
+            super::Delay::until(Instant::now()),
+            super::Delay::Tokio(_)
+        ));
+
+        let start = Instant::now();
+        let c = FuturesContract::new(Duration::from_millis(30), (), |_| -> usize { 5 });
+        assert!(matches!(c.await, Status::Completed(5)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn smol_delay_wakes_contract() {
+        assert!(matches!(
+            super::Delay::until(Instant::now()),
+            super::Delay::Smol(_)
+        ));
+
+        let start = Instant::now();
+        let c = FuturesContract::new(Duration::from_millis(30), (), |_| -> usize { 5 });
+        assert!(matches!(smol::block_on(c), Status::Completed(5)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(all(feature = "async-std", not(feature = "smol")))]
+    #[test]
+    fn async_std_delay_wakes_contract() {
+        assert!(matches!(
+            super::Delay::until(Instant::now()),
+            super::Delay::AsyncStd { .. }
+        ));
+
+        let start = Instant::now();
+        let c = FuturesContract::new(Duration::from_millis(30), (), |_| -> usize { 5 });
+        assert!(matches!(async_std::task::block_on(c), Status::Completed(5)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[cfg(all(feature = "async-std", not(feature = "smol")))]
+    #[test]
+    fn delay_polled_late() {
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let delay = super::Delay::until(deadline);
+
+        // The time spent before the first poll counts toward the deadline
+        std::thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        async_std::task::block_on(delay);
+        assert!(start.elapsed() < Duration::from_millis(100));
     }
 }
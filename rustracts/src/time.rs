@@ -1,29 +1,371 @@
-use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::ops;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::context::ContractContext;
+use crate::park::{Delay, Handle};
 
 use futures::{
     future::Future,
-    task::{Context, Poll},
+    task::{Context, Poll, Waker},
 };
 
+/// Future returned by [`Clock::sleep_until`](trait.Clock.html#tymethod.sleep_until).
+pub type ClockSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Longest delay before a timer waiting for a wall-clock [`Deadline`](enum.Deadline.html) notices
+/// that the system time jumped.
+pub const SYSTEM_TIME_RECHECK: Duration = Duration::from_secs(1);
+
+/// Source of time for [`Timer`](struct.Timer.html)s.
+pub trait Clock: Send + Sync {
+    /// Current instant on this clock.
+    fn now(&self) -> Instant;
+
+    /// Current wall-clock time, it can jump backward or forward unlike [`now`](#tymethod.now).
+    /// The system time by default.
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Future that completes once this clock has reached `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep;
+}
+
+/// Clock following the time of the runtime timers are polled on.
+///
+/// Sleeps are registered on the global [`Driver`](../park/struct.Driver.html). With the `tokio`
+/// feature timers polled inside a tokio runtime use its clock and timers instead, so they follow
+/// `tokio::time::pause` and `advance`. The `smol` and `async-std` features use their reactor,
+/// `smol` if both are enabled.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        #[cfg(feature = "tokio")]
+        {
+            tokio::time::Instant::now().into_std()
+        }
+        #[cfg(not(feature = "tokio"))]
+        {
+            Instant::now()
+        }
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        Box::pin(Delay::until(deadline))
+    }
+}
+
+/// Timers on an explicit driver follow the system time.
+impl Clock for Handle {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        Box::pin(Handle::sleep_until(self, deadline))
+    }
+}
+
+/// Clock that only moves when it is advanced, cloned instances share the same time.
+///
+/// Its wall-clock time moves along, it can also be set on its own to simulate a jump of the
+/// system time.
+///
+/// # Examples
+/// ```rust
+/// use std::time::Duration;
+/// use rustracts::time::{MockClock, Timer};
+///
+/// let clock = MockClock::new();
+/// let timer = Timer::with_clock(Duration::from_secs(3600), clock.clone());
+///
+/// clock.advance(Duration::from_secs(3600));
+/// futures::executor::block_on(timer); // Completes right away
+/// ```
+#[derive(Clone, Default)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockState>>,
+}
+
+struct MockState {
+    now: Instant,
+    system_now: SystemTime,
+    next_id: usize,
+    sleepers: HashMap<usize, (Instant, Waker)>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            now: Instant::now(),
+            system_now: SystemTime::now(),
+            next_id: 0,
+            sleepers: HashMap::new(),
+        }
+    }
+}
+
+impl MockClock {
+    /// Build a new clock starting at the current instant.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward, waking the sleeps that have reached their deadline.
+    pub fn advance(&self, duration: Duration) {
+        let woken: Vec<Waker> = {
+            let mut state = self.inner.lock().unwrap();
+            state.now += duration;
+            state.system_now += duration;
+            let now = state.now;
+            let due: Vec<usize> = state
+                .sleepers
+                .iter()
+                .filter(|(_, (deadline, _))| *deadline <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            due.into_iter()
+                .filter_map(|id| state.sleepers.remove(&id))
+                .map(|(_, waker)| waker)
+                .collect()
+        };
+        woken.into_iter().for_each(Waker::wake);
+    }
+
+    /// Set the wall-clock time without moving the instants, like a jump of the system time.
+    pub fn set_system_time(&self, time: SystemTime) {
+        self.inner.lock().unwrap().system_now = time;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.inner.lock().unwrap().system_now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        let id = {
+            let mut state = self.inner.lock().unwrap();
+            state.next_id += 1;
+            state.next_id
+        };
+        Box::pin(MockSleep {
+            clock: self.clone(),
+            id,
+            deadline,
+        })
+    }
+}
+
+struct MockSleep {
+    clock: MockClock,
+    id: usize,
+    deadline: Instant,
+}
+
+impl Future for MockSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.clock.inner.lock().unwrap();
+        if state.now >= self.deadline {
+            state.sleepers.remove(&self.id);
+            Poll::Ready(())
+        } else {
+            state
+                .sleepers
+                .insert(self.id, (self.deadline, cx.waker().clone()));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for MockSleep {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.clock.inner.lock() {
+            state.sleepers.remove(&self.id);
+        }
+    }
+}
+
+/// Point in time at which a [`Timer`](struct.Timer.html) expires.
+///
+/// Instants follow the monotonic time of the clock, they are only valid in the process that built
+/// them. Wall-clock deadlines can be stored and restored, they expire once the wall-clock time of
+/// the clock has reached them even if the system time jumps in between. Deadlines too far to be
+/// represented are never reached.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Deadline {
+    /// Instant on the monotonic time of the clock.
+    Instant(Instant),
+
+    /// Wall-clock time, checked again at least every [`SYSTEM_TIME_RECHECK`](constant.SYSTEM_TIME_RECHECK.html).
+    SystemTime(SystemTime),
+
+    /// Deadline past the range of the clock, the timer never expires.
+    Never,
+}
+
+impl Deadline {
+    /// Deadline `duration` from now on `clock`.
+    pub fn after<K>(duration: Duration, clock: &K) -> Self
+    where
+        K: Clock + ?Sized,
+    {
+        clock
+            .now()
+            .checked_add(duration)
+            .map_or(Deadline::Never, Deadline::Instant)
+    }
+
+    /// Time left before the deadline on `clock`, zero once it has passed.
+    pub fn remaining<K>(&self, clock: &K) -> Duration
+    where
+        K: Clock + ?Sized,
+    {
+        match self {
+            Deadline::Instant(at) => at.saturating_duration_since(clock.now()),
+            Deadline::SystemTime(at) => at
+                .duration_since(clock.system_now())
+                .unwrap_or(Duration::ZERO),
+            Deadline::Never => Duration::MAX,
+        }
+    }
+
+    // Instant to sleep until before checking the deadline again, none if it is never reached
+    fn wake_at<K>(&self, clock: &K) -> Option<Instant>
+    where
+        K: Clock + ?Sized,
+    {
+        match self {
+            Deadline::Instant(at) => Some(*at),
+            Deadline::SystemTime(_) => {
+                Some(clock.now() + std::cmp::min(self.remaining(clock), SYSTEM_TIME_RECHECK))
+            }
+            Deadline::Never => None,
+        }
+    }
+}
+
+/// The same point on the clock a duration later, [`Never`](#variant.Never) if it overflows.
+impl ops::Add<Duration> for Deadline {
+    type Output = Deadline;
+
+    fn add(self, duration: Duration) -> Self::Output {
+        match self {
+            Deadline::Instant(at) => at.checked_add(duration).map_or(Deadline::Never, From::from),
+            Deadline::SystemTime(at) => {
+                at.checked_add(duration).map_or(Deadline::Never, From::from)
+            }
+            Deadline::Never => Deadline::Never,
+        }
+    }
+}
+
+impl From<Instant> for Deadline {
+    fn from(at: Instant) -> Self {
+        Deadline::Instant(at)
+    }
+}
+
+impl From<SystemTime> for Deadline {
+    fn from(at: SystemTime) -> Self {
+        Deadline::SystemTime(at)
+    }
+}
+
 /// Timer future that will finish when it's time is done. Timers are also valid contract clauses.
+///
+/// The deadline is registered once on its [`Clock`](trait.Clock.html) which wakes the task on
+/// expiration, the [`SystemClock`](struct.SystemClock.html) is used by default.
 pub struct Timer {
-    creation: Instant,
+    deadline: Deadline,
+    /// Time between the construction or the last reset of the timer and its deadline. Writing it
+    /// does not move the deadline, [`reset`](#method.reset) the timer instead.
+    #[deprecated(note = "read `Timer::duration` and move the deadline with `Timer::reset`")]
     pub duration: Duration,
+
+    clock: Arc<dyn Clock>,
+    sleep: Option<ClockSleep>,
 }
 
 impl Timer {
-    /// Construct a new ContractTimer from a Duration
+    /// Construct a new ContractTimer from a Duration.
     pub fn new(duration: Duration) -> Self {
+        Self::with_clock(duration, SystemClock)
+    }
+
+    /// Construct a new ContractTimer from a Duration that follows the time of `clock`.
+    pub fn with_clock<K>(duration: Duration, clock: K) -> Self
+    where
+        K: Clock + 'static,
+    {
+        Self::with_deadline(Deadline::after(duration, &clock), clock)
+    }
+
+    /// Construct a new ContractTimer expiring at `deadline`.
+    pub fn at<D>(deadline: D) -> Self
+    where
+        D: Into<Deadline>,
+    {
+        Self::with_deadline(deadline, SystemClock)
+    }
+
+    /// Construct a new ContractTimer expiring at `deadline` on the time of `clock`.
+    #[allow(deprecated)]
+    pub fn with_deadline<D, K>(deadline: D, clock: K) -> Self
+    where
+        D: Into<Deadline>,
+        K: Clock + 'static,
+    {
+        let deadline = deadline.into();
         Self {
-            creation: Instant::now(),
-            duration,
+            deadline,
+            duration: deadline.remaining(&clock),
+            clock: Arc::new(clock),
+            sleep: None,
         }
     }
+
+    /// Point in time at which the timer expires.
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// Expire again at `deadline` on the same clock.
+    #[allow(deprecated)]
+    pub fn reset<D>(&mut self, deadline: D)
+    where
+        D: Into<Deadline>,
+    {
+        self.deadline = deadline.into();
+        self.duration = self.deadline.remaining(&*self.clock);
+        self.sleep = None;
+    }
+
+    /// Time between the construction or the last reset of the timer and its deadline.
+    #[allow(deprecated)]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
     /// Check wether the timer has expired.
     pub fn expired(&self) -> bool {
-        Instant::now().duration_since(self.creation) > self.duration
+        self.remaining() == Duration::ZERO
+    }
+
+    /// Time left before the timer expires, zero once it has.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.remaining(&*self.clock)
     }
 }
 
@@ -36,11 +378,121 @@ impl ContractContext for Timer {
 impl Future for Timer {
     type Output = ();
 
-    fn poll(self: std::pin::Pin<&mut Self>, _: &mut Context) -> Poll<Self::Output> {
-        if self.expired() {
-            Poll::Ready(())
-        } else {
-            Poll::Pending
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        while !this.expired() {
+            let sleep = match &mut this.sleep {
+                Some(sleep) => sleep,
+                None => match this.deadline.wake_at(&*this.clock) {
+                    Some(at) => this.sleep.insert(this.clock.sleep_until(at)),
+                    None => return Poll::Pending,
+                },
+            };
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            // Woken before the deadline or the system time jumped back, register again
+            this.sleep = None;
         }
+        Poll::Ready(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deadline, MockClock, Timer, SYSTEM_TIME_RECHECK};
+    use crate::time::Clock;
+
+    use futures::task::Poll;
+
+    use std::time::Duration;
+
+    #[test]
+    fn instant_deadline() {
+        let clock = MockClock::new();
+        let mut timer = Timer::with_deadline(clock.now() + Duration::from_secs(10), clock.clone());
+
+        crate::executor::block_on(async {
+            // The deadline does not drift with the construction of the timer
+            clock.advance(Duration::from_secs(4));
+            assert_eq!(timer.remaining(), Duration::from_secs(6));
+            assert_eq!(timer.duration(), Duration::from_secs(10));
+            assert!(futures::poll!(&mut timer).is_pending());
+
+            clock.advance(Duration::from_secs(6));
+            assert!(matches!(futures::poll!(&mut timer), Poll::Ready(())));
+        });
+
+        timer.reset(clock.now() + Duration::from_secs(5));
+        assert_eq!(timer.duration(), Duration::from_secs(5));
+
+        // The public field of earlier releases is still readable
+        #[allow(deprecated)]
+        let duration = timer.duration;
+        assert_eq!(duration, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn system_time_jumps_forward() {
+        let clock = MockClock::new();
+        let at = clock.system_now() + Duration::from_secs(3600);
+        let mut timer = Timer::with_deadline(at, clock.clone());
+        assert_eq!(timer.deadline(), Deadline::SystemTime(at));
+
+        crate::executor::block_on(async {
+            assert!(futures::poll!(&mut timer).is_pending());
+
+            // The wall clock reaches the deadline, the timer notices it at its next check
+            clock.set_system_time(at);
+            assert!(timer.expired());
+            clock.advance(SYSTEM_TIME_RECHECK);
+            assert!(matches!(futures::poll!(&mut timer), Poll::Ready(())));
+        });
+    }
+
+    #[test]
+    fn system_time_jumps_backward() {
+        let clock = MockClock::new();
+        let at = clock.system_now() + Duration::from_secs(10);
+        let mut timer = Timer::with_deadline(at, clock.clone());
+
+        crate::executor::block_on(async {
+            assert!(futures::poll!(&mut timer).is_pending());
+
+            // Ten seconds pass but the wall clock was set back by five
+            clock.set_system_time(clock.system_now() - Duration::from_secs(5));
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(timer.remaining(), Duration::from_secs(5));
+            assert!(futures::poll!(&mut timer).is_pending());
+
+            clock.advance(Duration::from_secs(5));
+            assert!(matches!(futures::poll!(&mut timer), Poll::Ready(())));
+        });
+    }
+
+    #[test]
+    fn never_expiring_timer() {
+        let clock = MockClock::new();
+        let mut timer = Timer::with_clock(Duration::MAX, clock.clone());
+        assert_eq!(timer.deadline(), Deadline::Never);
+        assert_eq!(timer.remaining(), Duration::MAX);
+
+        crate::executor::block_on(async {
+            assert!(futures::poll!(&mut timer).is_pending());
+            clock.advance(Duration::from_secs(u32::MAX as u64));
+            assert!(futures::poll!(&mut timer).is_pending());
+        });
+
+        // Deadlines moved out of range are never reached either
+        let at = Deadline::after(Duration::from_secs(1), &clock);
+        assert_eq!(at + Duration::MAX, Deadline::Never);
+        let at = Deadline::SystemTime(clock.system_now());
+        assert_eq!(at + Duration::MAX, Deadline::Never);
+
+        let mut timer = Timer::new(Duration::MAX);
+        assert_eq!(timer.deadline(), Deadline::Never);
+        crate::executor::block_on(async {
+            assert!(futures::poll!(&mut timer).is_pending());
+        });
     }
 }